@@ -1,45 +1,62 @@
 use std::{ io, env };
 use std::path::Path;
 use bytes::BytesMut;
-use anyhow::Context;
-use ritsu::Proactor;
+use ritsu::{ Proactor, LocalHandle };
 use ritsu::actions;
+use ritsu::actions::io::TrustedAsRawFd;
+use ritsu::buf::IoBuf;
 
 
 fn main() -> anyhow::Result<()> {
-    let target = env::args()
-        .nth(1)
-        .context("need file")?;
+    let target = env::args().nth(1);
 
     let mut proactor = Proactor::new()?;
     let handle = proactor.handle();
 
     ritsu::block_on(&mut proactor, async move {
-        let target = Path::new(&target);
-        let mut fd = actions::fs::open(&handle, target).await?;
-        let mut stdout = io::stdout();
-        let mut buf = BytesMut::with_capacity(32 << 10);
-
-        loop {
-            let (fd2, buf2) =
-                actions::io::read_buf(&handle, &mut Some(fd), buf, None).await?;
-            fd = fd2;
-            buf = buf2;
-
-            if buf.is_empty() {
-                break
-            }
+        match target {
+            Some(target) => {
+                let fd = actions::fs::open(&handle, Path::new(&target)).await?;
+                cat(&handle, fd).await
+            },
+            None => cat(&handle, io::stdin()).await
+        }
+    })??;
+
+    Ok(())
+}
+
+async fn cat<T: TrustedAsRawFd>(handle: &LocalHandle, mut fd: T) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let mut buf = BytesMut::with_capacity(32 << 10);
 
-            let (stdout2, buf2) =
-                actions::io::write_buf(&handle, &mut Some(stdout), buf, None).await?;
+    loop {
+        let (fd2, buf2, n) =
+            actions::io::read_buf(handle, &mut Some(fd), buf, None).await?;
+        fd = fd2;
+        buf = buf2;
+
+        if n == 0 {
+            break
+        }
+
+        // Write all of it, a write may be short.
+        let mut pos = 0;
+        while pos < buf.len() {
+            let (stdout2, buf2, n) =
+                actions::io::write_buf(handle, &mut Some(stdout), buf.slice(pos..), None).await?;
             stdout = stdout2;
-            buf = buf2;
+            buf = buf2.into_inner();
 
-            buf.clear();
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+
+            pos += n;
         }
 
-        Ok(()) as std::io::Result<()>
-    })??;
+        buf.clear();
+    }
 
     Ok(())
 }
@@ -0,0 +1,105 @@
+//! Compare `fs::copy` with the read then write loop of `cat.rs`.
+//!
+//! ```sh
+//! cargo run --release --example copy -- <from> <to>
+//! ```
+
+use std::{ io, env };
+use std::path::Path;
+use std::time::{ Duration, Instant };
+use bytes::BytesMut;
+use ritsu::{ Proactor, LocalHandle };
+use ritsu::actions;
+use ritsu::buf::IoBuf;
+
+
+const ROUNDS: usize = 5;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1);
+    let (from, to) = match (args.next(), args.next()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => anyhow::bail!("usage: copy <from> <to>")
+    };
+    let (from, to) = (Path::new(&from), Path::new(&to));
+
+    let mut proactor = Proactor::new()?;
+    let handle = proactor.handle();
+
+    ritsu::block_on(&mut proactor, async move {
+        // Warm the page cache, so that both read from memory.
+        read_write(&handle, from, to).await?;
+
+        // Alternate them and keep the best of each, writes are noisy.
+        let mut best = [None; 2];
+
+        for _ in 0..ROUNDS {
+            let now = Instant::now();
+            let n = read_write(&handle, from, to).await?;
+            keep(&mut best[0], n, now);
+
+            let now = Instant::now();
+            let n = ritsu::fs::copy(&handle, from, to).await?;
+            keep(&mut best[1], n, now);
+        }
+
+        report("read/write", best[0]);
+        report("fs::copy", best[1]);
+
+        Ok::<_, io::Error>(())
+    })??;
+
+    Ok(())
+}
+
+fn keep(best: &mut Option<(u64, Duration)>, n: u64, now: Instant) {
+    let elapsed = now.elapsed();
+
+    match best {
+        Some((_, best)) if *best <= elapsed => (),
+        _ => *best = Some((n, elapsed))
+    }
+}
+
+fn report(name: &str, best: Option<(u64, Duration)>) {
+    if let Some((n, elapsed)) = best {
+        let rate = n as f64 / elapsed.as_secs_f64() / (1 << 20) as f64;
+        println!("{:<12} {} bytes in {:?}, {:.0} MiB/s", name, n, elapsed, rate);
+    }
+}
+
+/// The loop of `cat.rs`, writing into a file.
+async fn read_write(handle: &LocalHandle, from: &Path, to: &Path) -> io::Result<u64> {
+    let mut fd = actions::fs::open(handle, from).await?;
+    let mut out = std::fs::File::create(to)?;
+    let mut buf = BytesMut::with_capacity(32 << 10);
+    let mut total = 0;
+
+    loop {
+        let (fd2, buf2, n) =
+            actions::io::read_buf(handle, &mut Some(fd), buf, None).await?;
+        fd = fd2;
+        buf = buf2;
+
+        if n == 0 {
+            return Ok(total);
+        }
+
+        let mut pos = 0;
+        while pos < buf.len() {
+            let (out2, buf2, n) =
+                actions::io::write_buf(handle, &mut Some(out), buf.slice(pos..), None).await?;
+            out = out2;
+            buf = buf2.into_inner();
+
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+
+            pos += n;
+        }
+
+        total += buf.len() as u64;
+        buf.clear();
+    }
+}
@@ -1,6 +1,7 @@
 use tokio::task::{ LocalSet, yield_now };
+use io_uring::opcode;
 use ritsu::Proactor;
-use ritsu::actions;
+use ritsu::actions::{ self, PushError };
 
 fn main() -> anyhow::Result<()> {
     let mut proactor = Proactor::new()?;
@@ -10,15 +11,26 @@ fn main() -> anyhow::Result<()> {
 
     ritsu::block_on(&mut proactor, async move {
         for _ in 0..500 {
+            let batch = handle.batch();
+
             for _ in 0..500 {
-                taskset.spawn_local(actions::nop(handle.clone()));
+                let nop_e = opcode::Nop::new().build();
+                let action = unsafe {
+                    actions::action(&batch, (), nop_e)
+                        .map_err(PushError::into_error)?
+                };
+
+                taskset.spawn_local(action);
             }
 
+            drop(batch);
             yield_now().await;
         }
 
         taskset.await;
-    })?;
+
+        Ok(()) as anyhow::Result<()>
+    })??;
 
     Ok(())
 }
@@ -1,34 +1,868 @@
-use std::io;
+use std::{ io, ptr };
 use std::fs::File;
 use std::path::Path;
 use std::ffi::CString;
+use std::mem::{ ManuallyDrop, MaybeUninit };
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{ self, AtomicBool };
+use std::os::unix::io::{ AsRawFd, FromRawFd, IntoRawFd, RawFd, OwnedFd };
 use io_uring::{ types, opcode };
+use crate::{ sqe, blocking, EMPTY_TOKEN };
+use crate::sqe::RawEntry;
 use crate::handle::Handle;
 use crate::actions::{ action, PushError };
+use crate::actions::io::{ TrustedAsRawFd, not_found };
+use crate::actions::options::ActionOptions;
 
 
+/// Options and flags which can be used to configure how a file is opened,
+/// like [`std::fs::OpenOptions`].
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    custom_flags: i32,
+    mode: libc::mode_t,
+    resolve: Option<ResolveFlags>
+}
+
+bitflags::bitflags!{
+    /// Path resolution flags for `openat2(2)`.
+    pub struct ResolveFlags: u64 {
+        const NO_XDEV = libc::RESOLVE_NO_XDEV;
+        const NO_MAGICLINKS = libc::RESOLVE_NO_MAGICLINKS;
+        const NO_SYMLINKS = libc::RESOLVE_NO_SYMLINKS;
+        const BENEATH = libc::RESOLVE_BENEATH;
+        const IN_ROOT = libc::RESOLVE_IN_ROOT;
+        const CACHED = libc::RESOLVE_CACHED;
+    }
+}
+
 pub async fn open<H: Handle>(handle: H, path: &Path) -> io::Result<File> {
-    let path = CString::new(path.as_os_str().as_bytes())?;
+    open_with(handle, path, &ActionOptions::new()).await
+}
 
-    let open_e = opcode::OpenAt::new(
-        types::Fd(libc::AT_FDCWD),
+/// Like [`open`], with `options` applied to its entry.
+pub async fn open_with<H: Handle>(handle: H, path: &Path, options: &ActionOptions)
+    -> io::Result<File>
+{
+    OpenOptions::new()
+        .read(true)
+        .open_with(handle, path, options)
+        .await
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            custom_flags: 0,
+            mode: 0o666,
+            resolve: None
+        }
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.write = write;
+        self
+    }
+
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.create = create;
+        self
+    }
+
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Pass custom flags to the `flags` argument of `open`,
+    /// such as `O_DIRECT` or `O_NOFOLLOW`.
+    ///
+    /// The access mode bits are ignored.
+    pub fn custom_flags(&mut self, flags: i32) -> &mut OpenOptions {
+        self.custom_flags = flags;
+        self
+    }
+
+    /// Sets the permissions used when a new file is created, default is `0o666`.
+    pub fn mode(&mut self, mode: u32) -> &mut OpenOptions {
+        self.mode = mode as _;
+        self
+    }
+
+    /// Open with `openat2(2)` and restrict how the path is resolved.
+    ///
+    /// `ResolveFlags::BENEATH` or `ResolveFlags::IN_ROOT` keeps an untrusted path
+    /// from escaping the directory it is resolved against.
+    pub fn resolve(&mut self, resolve: ResolveFlags) -> &mut OpenOptions {
+        self.resolve = Some(resolve);
+        self
+    }
+
+    pub async fn open<H: Handle>(&self, handle: H, path: &Path) -> io::Result<File> {
+        self.open_with(handle, path, &ActionOptions::new()).await
+    }
+
+    /// Like [`Self::open`], with `options` applied to its entry.
+    pub async fn open_with<H: Handle>(&self, handle: H, path: &Path, options: &ActionOptions)
+        -> io::Result<File>
+    {
+        self.open_at(handle, libc::AT_FDCWD, path, options).await
+    }
+
+    pub(crate) async fn open_at<H: Handle>(
+        &self,
+        handle: H,
+        dirfd: RawFd,
+        path: &Path,
+        options: &ActionOptions
+    )
+        -> io::Result<File>
+    {
+        let flags = libc::O_CLOEXEC
+            | self.access_mode()?
+            | self.creation_mode()?
+            | (self.custom_flags & !libc::O_ACCMODE);
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let dirfd = types::Fd(dirfd);
+
+        let cqe = if let Some(resolve) = self.resolve {
+            // `openat2` rejects a mode unless a file may be created.
+            let mode = if flags & libc::O_CREAT != 0 || flags & libc::O_TMPFILE == libc::O_TMPFILE {
+                self.mode
+            } else {
+                0
+            };
+            let how = Box::new(types::OpenHow::new()
+                .flags(flags as _)
+                .mode(mode as _)
+                .resolve(resolve.bits()));
+
+            let open_e = opcode::OpenAt2::new(dirfd, path.as_ptr(), &*how)
+                .build();
+
+            let (_, cqe) = unsafe {
+                action(handle, (path, how), options.apply(open_e))
+                    .map_err(PushError::into_error)?.await
+            };
+
+            cqe
+        } else {
+            let open_e = opcode::OpenAt::new(dirfd, path.as_ptr())
+                .flags(flags)
+                .mode(self.mode)
+                .build();
+
+            let (_, cqe) = unsafe {
+                action(handle, path, options.apply(open_e))
+                    .map_err(PushError::into_error)?.await
+            };
+
+            cqe
+        };
+
+        let ret = cqe.result();
+        if ret >= 0 {
+            Ok(unsafe {
+                File::from_raw_fd(ret)
+            })
+        } else {
+            Err(io::Error::from_raw_os_error(-ret))
+        }
+    }
+
+    fn access_mode(&self) -> io::Result<i32> {
+        match (self.read, self.write, self.append) {
+            (true, false, false) => Ok(libc::O_RDONLY),
+            (false, true, false) => Ok(libc::O_WRONLY),
+            (true, true, false) => Ok(libc::O_RDWR),
+            (false, _, true) => Ok(libc::O_WRONLY | libc::O_APPEND),
+            (true, _, true) => Ok(libc::O_RDWR | libc::O_APPEND),
+            (false, false, false) => Err(io::Error::from_raw_os_error(libc::EINVAL))
+        }
+    }
+
+    fn creation_mode(&self) -> io::Result<i32> {
+        match (self.write, self.append) {
+            (true, false) => (),
+            (false, false) => if self.truncate || self.create || self.create_new {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            },
+            (_, true) => if self.truncate && !self.create_new {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            }
+        }
+
+        Ok(match (self.create, self.truncate, self.create_new) {
+            (false, false, false) => 0,
+            (true, false, false) => libc::O_CREAT,
+            (false, true, false) => libc::O_TRUNC,
+            (true, true, false) => libc::O_CREAT | libc::O_TRUNC,
+            (_, _, true) => libc::O_CREAT | libc::O_EXCL
+        })
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
+    }
+}
+
+/// Directory fd that a path is resolved against.
+///
+/// It is held by the action and given back on completion, so an owned fd
+/// stays open while the request may still resolve the path. An action
+/// dropped before its completion leaks it.
+pub(crate) enum DirFd {
+    Cwd,
+    Borrowed(RawFd),
+    Owned(OwnedFd)
+}
+
+impl AsRawFd for DirFd {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            DirFd::Cwd => libc::AT_FDCWD,
+            DirFd::Borrowed(fd) => *fd,
+            DirFd::Owned(fd) => fd.as_raw_fd()
+        }
+    }
+}
+
+bitflags::bitflags!{
+    /// Flags for [`allocate`], see `fallocate(2)`.
+    pub struct AllocateFlags: i32 {
+        const KEEP_SIZE = libc::FALLOC_FL_KEEP_SIZE;
+        const PUNCH_HOLE = libc::FALLOC_FL_PUNCH_HOLE;
+        const ZERO_RANGE = libc::FALLOC_FL_ZERO_RANGE;
+        const COLLAPSE_RANGE = libc::FALLOC_FL_COLLAPSE_RANGE;
+    }
+}
+
+/// Manipulate the allocated disk space of a file.
+///
+/// `PUNCH_HOLE` must be combined with `KEEP_SIZE`.
+pub async fn allocate<H: Handle, T: TrustedAsRawFd>(
+    handle: H,
+    fd: &mut Option<T>,
+    mode: AllocateFlags,
+    offset: u64,
+    len: u64
+)
+    -> io::Result<T>
+{
+    allocate_with(handle, fd, mode, offset, len, &ActionOptions::new()).await
+}
+
+/// Like [`allocate`], with `options` applied to its entry.
+pub async fn allocate_with<H: Handle, T: TrustedAsRawFd>(
+    handle: H,
+    fd: &mut Option<T>,
+    mode: AllocateFlags,
+    offset: u64,
+    len: u64,
+    options: &ActionOptions
+)
+    -> io::Result<T>
+{
+    let fd2 = match fd.take() {
+        Some(fd) => fd,
+        None => return Err(not_found())
+    };
+
+    let fallocate_e = opcode::Fallocate64::new(types::Fd(fd2.as_raw_fd()), len as _)
+        .offset64(offset as _)
+        .mode(mode.bits())
+        .build();
+
+    let (fd2, cqe) = unsafe {
+        action(handle, fd2, options.apply(fallocate_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok(fd2)
+    } else {
+        *fd = Some(fd2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Truncate or extend a file to `size` bytes.
+///
+/// Uses `IORING_OP_FTRUNCATE`, and falls back to the blocking pool
+/// on kernels that lack it.
+pub async fn set_len<H: Handle, T: TrustedAsRawFd>(
+    handle: H,
+    fd: &mut Option<T>,
+    size: u64
+)
+    -> io::Result<T>
+{
+    set_len_with(handle, fd, size, &ActionOptions::new()).await
+}
+
+/// Like [`set_len`], with `options` applied to its entry.
+pub async fn set_len_with<H: Handle, T: TrustedAsRawFd>(
+    handle: H,
+    fd: &mut Option<T>,
+    size: u64,
+    options: &ActionOptions
+)
+    -> io::Result<T>
+{
+    // `ftruncate` takes a signed length.
+    if size > i64::MAX as u64 {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+
+    let fd2 = match fd.take() {
+        Some(fd) => fd,
+        None => return Err(not_found())
+    };
+
+    let (fd2, ret) = if FTRUNCATE_UNSUPPORTED.load(atomic::Ordering::Relaxed) {
+        (fd2, -libc::EINVAL)
+    } else {
+        let ftruncate_e = RawEntry {
+            opcode: sqe::IORING_OP_FTRUNCATE,
+            fd: fd2.as_raw_fd(),
+            off: size,
+            ..Default::default()
+        }
+            .build();
+
+        let (fd2, cqe) = unsafe {
+            action(handle, fd2, options.apply(ftruncate_e))
+                .map_err(PushError::into_error)?.await
+        };
+
+        (fd2, cqe.result())
+    };
+
+    if ret >= 0 {
+        return Ok(fd2);
+    }
+
+    // Older kernels reject unknown opcodes with `EINVAL`,
+    // but so they do a fd that cannot be truncated.
+    if ret != -libc::EINVAL {
+        *fd = Some(fd2);
+        return Err(io::Error::from_raw_os_error(-ret));
+    }
+
+    // The job owns a duplicate, so the fd cannot be closed and reused
+    // while it is queued, even if this future is dropped.
+    let dupfd = match check_truncate(fd2.as_raw_fd()) {
+        Ok(dupfd) => dupfd,
+        Err(err) => {
+            *fd = Some(fd2);
+            return Err(err);
+        }
+    };
+
+    FTRUNCATE_UNSUPPORTED.store(true, atomic::Ordering::Relaxed);
+
+    let result = blocking::spawn(move || {
+        if unsafe { libc::ftruncate(dupfd.as_raw_fd(), size as _) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }).await;
+
+    match result {
+        Ok(()) => Ok(fd2),
+        Err(err) => {
+            *fd = Some(fd2);
+            Err(err)
+        }
+    }
+}
+
+/// Set once the kernel is known to lack `IORING_OP_FTRUNCATE`.
+static FTRUNCATE_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Fail like `ftruncate` would for a fd that cannot be truncated,
+/// otherwise duplicate it.
+fn check_truncate(fd: RawFd) -> io::Result<OwnedFd> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let stat = unsafe { stat.assume_init() };
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+
+    if stat.st_mode & libc::S_IFMT != libc::S_IFREG
+        || flags & libc::O_ACCMODE == libc::O_RDONLY
+    {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+
+    let dupfd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+
+    if dupfd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { OwnedFd::from_raw_fd(dupfd) })
+}
+
+bitflags::bitflags!{
+    /// Flags for [`rename`], see `renameat2(2)`.
+    pub struct RenameFlags: u32 {
+        const NOREPLACE = libc::RENAME_NOREPLACE;
+        const EXCHANGE = libc::RENAME_EXCHANGE;
+    }
+}
+
+/// Create a new, empty directory.
+pub async fn create_dir<H: Handle>(handle: H, path: &Path) -> io::Result<()> {
+    create_dir_with(handle, path, &ActionOptions::new()).await
+}
+
+/// Like [`create_dir`], with `options` applied to its entry.
+pub async fn create_dir_with<H: Handle>(handle: H, path: &Path, options: &ActionOptions)
+    -> io::Result<()>
+{
+    create_dir_at(handle, DirFd::Cwd, path, options).await.1
+}
+
+pub(crate) async fn create_dir_at<H: Handle>(
+    handle: H,
+    dirfd: DirFd,
+    path: &Path,
+    options: &ActionOptions
+)
+    -> (DirFd, io::Result<()>)
+{
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(err) => return (dirfd, Err(err.into()))
+    };
+
+    let mkdir_e = opcode::MkDirAt::new(
+        types::Fd(dirfd.as_raw_fd()),
+        path.as_ptr()
+    )
+        .mode(0o777)
+        .build();
+
+    let ((dirfd, _), cqe) = unsafe {
+        match action(handle, (dirfd, path), options.apply(mkdir_e)) {
+            Ok(action) => action.await,
+            Err(err) => {
+                let (err, (dirfd, ..)) = err.into_inner();
+                return (dirfd, Err(err));
+            }
+        }
+    };
+
+    (dirfd, result(cqe.result()))
+}
+
+/// Remove a file.
+pub async fn remove_file<H: Handle>(handle: H, path: &Path) -> io::Result<()> {
+    remove_file_with(handle, path, &ActionOptions::new()).await
+}
+
+/// Like [`remove_file`], with `options` applied to its entry.
+pub async fn remove_file_with<H: Handle>(handle: H, path: &Path, options: &ActionOptions)
+    -> io::Result<()>
+{
+    unlink_at(handle, DirFd::Cwd, path, 0, options).await.1
+}
+
+/// Remove an empty directory.
+pub async fn remove_dir<H: Handle>(handle: H, path: &Path) -> io::Result<()> {
+    remove_dir_with(handle, path, &ActionOptions::new()).await
+}
+
+/// Like [`remove_dir`], with `options` applied to its entry.
+pub async fn remove_dir_with<H: Handle>(handle: H, path: &Path, options: &ActionOptions)
+    -> io::Result<()>
+{
+    unlink_at(handle, DirFd::Cwd, path, libc::AT_REMOVEDIR, options).await.1
+}
+
+pub(crate) async fn unlink_at<H: Handle>(
+    handle: H,
+    dirfd: DirFd,
+    path: &Path,
+    flags: i32,
+    options: &ActionOptions
+)
+    -> (DirFd, io::Result<()>)
+{
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(err) => return (dirfd, Err(err.into()))
+    };
+
+    let unlink_e = opcode::UnlinkAt::new(
+        types::Fd(dirfd.as_raw_fd()),
         path.as_ptr()
+    )
+        .flags(flags)
+        .build();
+
+    let ((dirfd, _), cqe) = unsafe {
+        match action(handle, (dirfd, path), options.apply(unlink_e)) {
+            Ok(action) => action.await,
+            Err(err) => {
+                let (err, (dirfd, ..)) = err.into_inner();
+                return (dirfd, Err(err));
+            }
+        }
+    };
+
+    (dirfd, result(cqe.result()))
+}
+
+/// Rename a file or directory, replacing `to` unless `flags` say otherwise.
+pub async fn rename<H: Handle>(handle: H, from: &Path, to: &Path, flags: RenameFlags)
+    -> io::Result<()>
+{
+    rename_with(handle, from, to, flags, &ActionOptions::new()).await
+}
+
+/// Like [`rename`], with `options` applied to its entry.
+pub async fn rename_with<H: Handle>(
+    handle: H,
+    from: &Path,
+    to: &Path,
+    flags: RenameFlags,
+    options: &ActionOptions
+)
+    -> io::Result<()>
+{
+    rename_at(handle, (DirFd::Cwd, DirFd::Cwd), from, to, flags, options).await.1
+}
+
+/// `dirfds` are the directories of `from` and `to`.
+pub(crate) async fn rename_at<H: Handle>(
+    handle: H,
+    dirfds: (DirFd, DirFd),
+    from: &Path,
+    to: &Path,
+    flags: RenameFlags,
+    options: &ActionOptions
+)
+    -> ((DirFd, DirFd), io::Result<()>)
+{
+    let paths = CString::new(from.as_os_str().as_bytes())
+        .and_then(|from| Ok((from, CString::new(to.as_os_str().as_bytes())?)));
+    let (from, to) = match paths {
+        Ok(paths) => paths,
+        Err(err) => return (dirfds, Err(err.into()))
+    };
+
+    let rename_e = opcode::RenameAt::new(
+        types::Fd(dirfds.0.as_raw_fd()),
+        from.as_ptr(),
+        types::Fd(dirfds.1.as_raw_fd()),
+        to.as_ptr()
+    )
+        .flags(flags.bits())
+        .build();
+
+    let ((dirfds, ..), cqe) = unsafe {
+        match action(handle, (dirfds, from, to), options.apply(rename_e)) {
+            Ok(action) => action.await,
+            Err(err) => {
+                let (err, (dirfds, ..)) = err.into_inner();
+                return (dirfds, Err(err));
+            }
+        }
+    };
+
+    (dirfds, result(cqe.result()))
+}
+
+/// Create a new hard link `link` pointing to `original`.
+pub async fn hard_link<H: Handle>(handle: H, original: &Path, link: &Path) -> io::Result<()> {
+    hard_link_with(handle, original, link, &ActionOptions::new()).await
+}
+
+/// Like [`hard_link`], with `options` applied to its entry.
+pub async fn hard_link_with<H: Handle>(
+    handle: H,
+    original: &Path,
+    link: &Path,
+    options: &ActionOptions
+)
+    -> io::Result<()>
+{
+    let original = CString::new(original.as_os_str().as_bytes())?;
+    let link = CString::new(link.as_os_str().as_bytes())?;
+
+    let link_e = opcode::LinkAt::new(
+        types::Fd(libc::AT_FDCWD),
+        original.as_ptr(),
+        types::Fd(libc::AT_FDCWD),
+        link.as_ptr()
     )
         .build();
 
     let (_, cqe) = unsafe {
-        action(handle, path, open_e)
+        action(handle, (original, link), options.apply(link_e))
             .map_err(PushError::into_error)?.await
     };
 
     let ret = cqe.result();
     if ret >= 0 {
-        Ok(unsafe {
-            File::from_raw_fd(ret)
-        })
+        Ok(())
     } else {
         Err(io::Error::from_raw_os_error(-ret))
     }
 }
+
+/// Create a new symbolic link `link` pointing to `original`.
+pub async fn symlink<H: Handle>(handle: H, original: &Path, link: &Path) -> io::Result<()> {
+    symlink_with(handle, original, link, &ActionOptions::new()).await
+}
+
+/// Like [`symlink`], with `options` applied to its entry.
+pub async fn symlink_with<H: Handle>(
+    handle: H,
+    original: &Path,
+    link: &Path,
+    options: &ActionOptions
+)
+    -> io::Result<()>
+{
+    let original = CString::new(original.as_os_str().as_bytes())?;
+    let link = CString::new(link.as_os_str().as_bytes())?;
+
+    let symlink_e = opcode::SymlinkAt::new(
+        types::Fd(libc::AT_FDCWD),
+        original.as_ptr(),
+        link.as_ptr()
+    )
+        .build();
+
+    let (_, cqe) = unsafe {
+        action(handle, (original, link), options.apply(symlink_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Query file metadata, following symlinks.
+pub async fn statx<H: Handle>(handle: H, path: &Path) -> io::Result<libc::statx> {
+    statx_with(handle, path, &ActionOptions::new()).await
+}
+
+/// Like [`statx`], with `options` applied to its entry.
+pub async fn statx_with<H: Handle>(handle: H, path: &Path, options: &ActionOptions)
+    -> io::Result<libc::statx>
+{
+    statx_at(handle, DirFd::Cwd, path, 0, options).await.1
+}
+
+pub(crate) async fn statx_at<H: Handle>(
+    handle: H,
+    dirfd: DirFd,
+    path: &Path,
+    flags: i32,
+    options: &ActionOptions
+)
+    -> (DirFd, io::Result<libc::statx>)
+{
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(err) => return (dirfd, Err(err.into()))
+    };
+    let mut statxbuf: Box<MaybeUninit<libc::statx>> = Box::new(MaybeUninit::uninit());
+
+    let statx_e = opcode::Statx::new(
+        types::Fd(dirfd.as_raw_fd()),
+        path.as_ptr(),
+        statxbuf.as_mut_ptr().cast()
+    )
+        .flags(flags | libc::AT_STATX_SYNC_AS_STAT)
+        .mask(libc::STATX_ALL)
+        .build();
+
+    let ((dirfd, _, statxbuf), cqe) = unsafe {
+        match action(handle, (dirfd, path, statxbuf), options.apply(statx_e)) {
+            Ok(action) => action.await,
+            Err(err) => {
+                let (err, (dirfd, ..)) = err.into_inner();
+                return (dirfd, Err(err));
+            }
+        }
+    };
+
+    let ret = cqe.result();
+    let ret = if ret >= 0 {
+        Ok(unsafe { statxbuf.assume_init_read() })
+    } else {
+        Err(io::Error::from_raw_os_error(-ret))
+    };
+
+    (dirfd, ret)
+}
+
+#[inline]
+fn result(ret: i32) -> io::Result<()> {
+    if ret >= 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Close a file descriptor through the ring,
+/// so that a slow `close(2)` (e.g. on NFS or FUSE) does not block the thread.
+pub async fn close<H: Handle, T: IntoRawFd>(handle: H, fd: T) -> io::Result<()> {
+    close_with(handle, fd, &ActionOptions::new()).await
+}
+
+/// Like [`close`], with `options` applied to its entry.
+pub async fn close_with<H: Handle, T: IntoRawFd>(handle: H, fd: T, options: &ActionOptions)
+    -> io::Result<()>
+{
+    let fd = fd.into_raw_fd();
+
+    let close_e = opcode::Close::new(types::Fd(fd))
+        .build();
+
+    let (_, cqe) = unsafe {
+        match action(handle, (), options.apply(close_e)) {
+            Ok(action) => action.await,
+            Err(err) => {
+                libc::close(fd);
+                return Err(err.into_error());
+            }
+        }
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Close a file descriptor without waiting for the result.
+///
+/// The entry is submitted right away, so the fd does not linger until the next park.
+/// Falls back to a blocking `close(2)` if the entry cannot be pushed.
+pub(crate) fn close_detached<H: Handle>(handle: H, fd: RawFd) {
+    let close_e = opcode::Close::new(types::Fd(fd))
+        .build()
+        .user_data(EMPTY_TOKEN);
+
+    unsafe {
+        if handle.push(&close_e).is_err() {
+            libc::close(fd);
+            return
+        }
+    }
+
+    // A failed submit leaves the entry queued for the next one.
+    let _ = handle.submit();
+}
+
+/// An owned fd that is closed through the ring when dropped.
+///
+/// Wraps a [`File`], a socket, a [`PipeReader`](crate::PipeReader)
+/// or a pipe of a child process, so that dropping it does not block
+/// the thread on `close(2)`. It can be put in a [`SharedFd`](crate::actions::io::SharedFd)
+/// to close the shared fd the same way.
+pub struct CloseOnDrop<H: Handle, T: IntoRawFd> {
+    handle: H,
+    fd: ManuallyDrop<T>
+}
+
+impl<H: Handle, T: IntoRawFd> CloseOnDrop<H, T> {
+    #[inline]
+    pub fn new(handle: H, fd: T) -> CloseOnDrop<H, T> {
+        CloseOnDrop { handle, fd: ManuallyDrop::new(fd) }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.fd
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.fd
+    }
+
+    /// Returns the fd, which is then closed by its own drop.
+    pub fn into_inner(this: CloseOnDrop<H, T>) -> T {
+        CloseOnDrop::into_parts(this).1
+    }
+
+    /// Close the fd and wait for the result.
+    pub async fn close(self) -> io::Result<()> {
+        let (handle, fd) = CloseOnDrop::into_parts(self);
+        close(handle, fd).await
+    }
+
+    fn into_parts(this: CloseOnDrop<H, T>) -> (H, T) {
+        let mut this = ManuallyDrop::new(this);
+
+        unsafe {
+            (ptr::read(&this.handle), ManuallyDrop::take(&mut this.fd))
+        }
+    }
+}
+
+impl<H: Handle, T: IntoRawFd + AsRawFd> AsRawFd for CloseOnDrop<H, T> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+unsafe impl<H: Handle + 'static, T: IntoRawFd + TrustedAsRawFd> TrustedAsRawFd
+    for CloseOnDrop<H, T> {}
+
+impl<H: Handle, T: IntoRawFd> Drop for CloseOnDrop<H, T> {
+    fn drop(&mut self) {
+        let fd = unsafe { ManuallyDrop::take(&mut self.fd) };
+        close_detached(&self.handle, fd.into_raw_fd());
+    }
+}
@@ -1,100 +1,786 @@
-use std::io;
-use std::os::unix::io::AsRawFd;
-use bytes::{ Buf, BufMut };
+use std::{ io, cmp };
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::ops::{ Bound, RangeBounds };
+use std::os::unix::io::{ AsRawFd, FromRawFd, RawFd, OwnedFd };
+use bytes::BytesMut;
 use io_uring::{ types, opcode };
 use crate::handle::Handle;
-use crate::actions::{ action, PushError };
+use crate::buf::{ IoBuf, IoBufMut, FixedIoBuf };
+use crate::actions::{ action, Chain, PushError };
+use crate::actions::options::ActionOptions;
 
 
+/// A file descriptor that can be handed to the kernel.
+///
+/// Actions take the value by move and only give it back after the
+/// completion arrived, so the kernel never sees a fd that has been closed
+/// or reused while the request is in flight.
+///
+/// # Safety
+///
+/// The fd returned by `as_raw_fd` must stay open and refer to the same file
+/// for as long as the value is alive, and must always be the same number.
+/// It must not be closed by anything but dropping the value.
 pub unsafe trait TrustedAsRawFd: AsRawFd + 'static {}
 
 unsafe impl TrustedAsRawFd for std::fs::File {}
+unsafe impl TrustedAsRawFd for std::io::Stdin {}
 unsafe impl TrustedAsRawFd for std::io::Stdout {}
 unsafe impl TrustedAsRawFd for std::io::Stderr {}
 unsafe impl TrustedAsRawFd for std::net::TcpStream {}
+unsafe impl TrustedAsRawFd for std::net::TcpListener {}
+unsafe impl TrustedAsRawFd for std::net::UdpSocket {}
+unsafe impl TrustedAsRawFd for std::os::unix::net::UnixStream {}
+unsafe impl TrustedAsRawFd for std::os::unix::net::UnixListener {}
+unsafe impl TrustedAsRawFd for std::os::unix::net::UnixDatagram {}
+unsafe impl TrustedAsRawFd for std::process::ChildStdin {}
+unsafe impl TrustedAsRawFd for std::process::ChildStdout {}
+unsafe impl TrustedAsRawFd for std::process::ChildStderr {}
+unsafe impl TrustedAsRawFd for OwnedFd {}
+unsafe impl<T: TrustedAsRawFd> TrustedAsRawFd for Box<T> {}
 
-pub async fn read_buf<H: Handle, T: TrustedAsRawFd, B: BufMut + 'static>(
+/// A raw fd owned elsewhere, for fd types that cannot implement [`TrustedAsRawFd`].
+///
+/// It does not close the fd on drop.
+#[derive(Debug)]
+pub struct UnsafeFd(RawFd);
+
+impl UnsafeFd {
+    /// # Safety
+    ///
+    /// The fd must stay open and refer to the same file until this value
+    /// and every action it was given to have been dropped or completed.
+    /// An action dropped before its completion keeps the value alive forever,
+    /// so the fd must then never be closed.
+    #[inline]
+    pub unsafe fn new(fd: RawFd) -> UnsafeFd {
+        UnsafeFd(fd)
+    }
+}
+
+impl AsRawFd for UnsafeFd {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+unsafe impl TrustedAsRawFd for UnsafeFd {}
+
+/// A reference counted fd that several in-flight actions can hold at once.
+///
+/// Every action is given its own clone, so the fd is closed only after
+/// the last clone is dropped and the last action that holds one has completed.
+/// This allows reading and writing a socket at the same time,
+/// or several positional reads on one file.
+///
+/// Share a [`CloseOnDrop`](crate::actions::fs::CloseOnDrop) to close the fd through the ring.
+#[derive(Debug)]
+pub struct SharedFd<T>(Arc<T>);
+
+impl<T: TrustedAsRawFd> SharedFd<T> {
+    #[inline]
+    pub fn new(fd: T) -> SharedFd<T> {
+        SharedFd(Arc::new(fd))
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns the fd if this is the last reference,
+    /// that is no other clone exists and no action holds one.
+    #[inline]
+    pub fn try_unwrap(this: SharedFd<T>) -> Result<T, SharedFd<T>> {
+        Arc::try_unwrap(this.0).map_err(SharedFd)
+    }
+
+    /// Number of references, including the ones held by in-flight actions.
+    #[inline]
+    pub fn ref_count(this: &SharedFd<T>) -> usize {
+        Arc::strong_count(&this.0)
+    }
+}
+
+impl<T> Clone for SharedFd<T> {
+    #[inline]
+    fn clone(&self) -> SharedFd<T> {
+        SharedFd(self.0.clone())
+    }
+}
+
+impl<T: TrustedAsRawFd> From<T> for SharedFd<T> {
+    #[inline]
+    fn from(fd: T) -> SharedFd<T> {
+        SharedFd::new(fd)
+    }
+}
+
+impl<T: AsRawFd> AsRawFd for SharedFd<T> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+unsafe impl<T: TrustedAsRawFd> TrustedAsRawFd for SharedFd<T> {}
+
+/// Read into `buf` from its start, up to `bytes_total` bytes,
+/// returns the number of bytes read, which are marked as initialized.
+///
+/// To append to the initialized part, pass `buf.slice(buf.bytes_init()..)`.
+pub async fn read_buf<H: Handle, T: TrustedAsRawFd, B: IoBufMut>(
     handle: H,
     fd: &mut Option<T>,
-    mut buf: B,
+    buf: B,
     offset: Option<u32>
 )
-    -> io::Result<(T, B)>
+    -> io::Result<(T, B, usize)>
+{
+    read_buf_with(handle, fd, buf, offset, &ActionOptions::new()).await
+}
+
+/// Like [`read_buf`], with `options` applied to its entry.
+pub async fn read_buf_with<H: Handle, T: TrustedAsRawFd, B: IoBufMut>(
+    handle: H,
+    fd: &mut Option<T>,
+    mut buf: B,
+    offset: Option<u32>,
+    options: &ActionOptions
+)
+    -> io::Result<(T, B, usize)>
 {
     let fd2 = match fd.take() {
         Some(fd) => fd,
         None => return Err(not_found())
     };
 
-    let chunk = buf.chunk_mut();
-
     let read_e = opcode::Read::new(
         types::Fd(fd2.as_raw_fd()),
-        chunk.as_mut_ptr(),
-        chunk.len() as _
+        buf.stable_mut_ptr(),
+        buf.bytes_total() as _
     )
         .offset(offset.map(|offset| offset as _).unwrap_or(-1))
         .build();
 
     let ((fd2, mut buf), cqe) = unsafe {
-        action(handle, (fd2, buf), read_e)
+        action(&handle, (fd2, buf), options.apply(read_e))
             .map_err(PushError::into_error)?.await
     };
 
     let ret = cqe.result();
     if ret >= 0 {
+        if let Some(metrics) = handle.metrics() {
+            metrics.on_read(ret as _);
+        }
+
         unsafe {
-            buf.advance_mut(ret as _);
+            buf.set_init(ret as _);
         }
 
-        Ok((fd2, buf))
+        Ok((fd2, buf, ret as _))
     } else {
         *fd = Some(fd2);
         Err(io::Error::from_raw_os_error(-ret))
     }
 }
 
-pub async fn write_buf<H: Handle, T: TrustedAsRawFd, B: Buf + 'static>(
+/// Write the initialized part of `buf`, returns the number of bytes written.
+pub async fn write_buf<H: Handle, T: TrustedAsRawFd, B: IoBuf>(
     handle: H,
     fd: &mut Option<T>,
     buf: B,
     offset: Option<u32>
 )
-    -> io::Result<(T, B)>
+    -> io::Result<(T, B, usize)>
+{
+    write_buf_with(handle, fd, buf, offset, &ActionOptions::new()).await
+}
+
+/// Like [`write_buf`], with `options` applied to its entry.
+pub async fn write_buf_with<H: Handle, T: TrustedAsRawFd, B: IoBuf>(
+    handle: H,
+    fd: &mut Option<T>,
+    buf: B,
+    offset: Option<u32>,
+    options: &ActionOptions
+)
+    -> io::Result<(T, B, usize)>
 {
     let fd2 = match fd.take() {
         Some(fd) => fd,
         None => return Err(not_found())
     };
 
-    let chunk = buf.chunk();
-
     let write_e = opcode::Write::new(
         types::Fd(fd2.as_raw_fd()),
-        chunk.as_ptr(),
-        chunk.len() as _
+        buf.stable_ptr(),
+        buf.bytes_init() as _
     )
         .offset(offset.map(|offset| offset as _).unwrap_or(-1))
         .build();
 
+    let ((fd2, buf), cqe) = unsafe {
+        action(&handle, (fd2, buf), options.apply(write_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        if let Some(metrics) = handle.metrics() {
+            metrics.on_write(ret as _);
+        }
+
+        Ok((fd2, buf, ret as _))
+    } else {
+        *fd = Some(fd2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Like [`read_buf`], with a buffer registered with the ring.
+pub async fn read_fixed<H: Handle, T: TrustedAsRawFd, B: IoBufMut + FixedIoBuf>(
+    handle: H,
+    fd: &mut Option<T>,
+    buf: B,
+    offset: Option<u64>
+)
+    -> io::Result<(T, B, usize)>
+{
+    read_fixed_with(handle, fd, buf, offset, &ActionOptions::new()).await
+}
+
+/// Like [`read_fixed`], with `options` applied to its entry.
+pub async fn read_fixed_with<H: Handle, T: TrustedAsRawFd, B: IoBufMut + FixedIoBuf>(
+    handle: H,
+    fd: &mut Option<T>,
+    mut buf: B,
+    offset: Option<u64>,
+    options: &ActionOptions
+)
+    -> io::Result<(T, B, usize)>
+{
+    let fd2 = match fd.take() {
+        Some(fd) => fd,
+        None => return Err(not_found())
+    };
+
+    let read_e = opcode::ReadFixed::new(
+        types::Fd(fd2.as_raw_fd()),
+        buf.stable_mut_ptr(),
+        buf.bytes_total() as _,
+        buf.buf_index()
+    )
+        .offset64(offset.map(|offset| offset as _).unwrap_or(-1))
+        .build();
+
     let ((fd2, mut buf), cqe) = unsafe {
-        action(handle, (fd2, buf), write_e)
+        action(&handle, (fd2, buf), options.apply(read_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        if let Some(metrics) = handle.metrics() {
+            metrics.on_read(ret as _);
+        }
+
+        unsafe {
+            buf.set_init(ret as _);
+        }
+
+        Ok((fd2, buf, ret as _))
+    } else {
+        *fd = Some(fd2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Like [`write_buf`], with a buffer registered with the ring.
+pub async fn write_fixed<H: Handle, T: TrustedAsRawFd, B: FixedIoBuf>(
+    handle: H,
+    fd: &mut Option<T>,
+    buf: B,
+    offset: Option<u64>
+)
+    -> io::Result<(T, B, usize)>
+{
+    write_fixed_with(handle, fd, buf, offset, &ActionOptions::new()).await
+}
+
+/// Like [`write_fixed`], with `options` applied to its entry.
+pub async fn write_fixed_with<H: Handle, T: TrustedAsRawFd, B: FixedIoBuf>(
+    handle: H,
+    fd: &mut Option<T>,
+    buf: B,
+    offset: Option<u64>,
+    options: &ActionOptions
+)
+    -> io::Result<(T, B, usize)>
+{
+    let fd2 = match fd.take() {
+        Some(fd) => fd,
+        None => return Err(not_found())
+    };
+
+    let write_e = opcode::WriteFixed::new(
+        types::Fd(fd2.as_raw_fd()),
+        buf.stable_ptr(),
+        buf.bytes_init() as _,
+        buf.buf_index()
+    )
+        .offset64(offset.map(|offset| offset as _).unwrap_or(-1))
+        .build();
+
+    let ((fd2, buf), cqe) = unsafe {
+        action(&handle, (fd2, buf), options.apply(write_e))
             .map_err(PushError::into_error)?.await
     };
 
     let ret = cqe.result();
     if ret >= 0 {
-        buf.advance(ret as _);
+        if let Some(metrics) = handle.metrics() {
+            metrics.on_write(ret as _);
+        }
 
-        Ok((fd2, buf))
+        Ok((fd2, buf, ret as _))
     } else {
         *fd = Some(fd2);
         Err(io::Error::from_raw_os_error(-ret))
     }
 }
 
+/// Reads lines from a fd, see [`lines`].
+pub struct Lines<H: Handle, T: TrustedAsRawFd> {
+    handle: H,
+    fd: Option<T>,
+    buf: BytesMut,
+    eof: bool
+}
+
+/// Read `fd` line by line, for pipes, ttys and other streams.
+pub fn lines<H: Handle, T: TrustedAsRawFd>(handle: H, fd: T) -> Lines<H, T> {
+    Lines {
+        handle,
+        fd: Some(fd),
+        buf: BytesMut::new(),
+        eof: false
+    }
+}
+
+impl<H: Handle> Lines<H, io::Stdin> {
+    /// Read the standard input line by line.
+    pub fn stdin(handle: H) -> Lines<H, io::Stdin> {
+        lines(handle, io::stdin())
+    }
+}
+
+impl<H: Handle, T: TrustedAsRawFd> Lines<H, T> {
+    /// Returns the next line without the line ending,
+    /// or `None` at end of file.
+    pub async fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+                let mut line = self.buf.split_to(pos + 1);
+                line.truncate(pos);
+
+                if line.last() == Some(&b'\r') {
+                    line.truncate(pos - 1);
+                }
+
+                return into_string(line).map(Some);
+            }
+
+            if self.eof {
+                return if self.buf.is_empty() {
+                    Ok(None)
+                } else {
+                    into_string(self.buf.split()).map(Some)
+                };
+            }
+
+            // Read into the spare capacity, so that a failed read
+            // leaves what is buffered in place.
+            self.buf.reserve(8 << 10);
+            let spare = self.buf.split_off(self.buf.len());
+
+            let (fd, spare, n) = read_buf(&self.handle, &mut self.fd, spare, None).await?;
+            self.eof = n == 0;
+            self.fd = Some(fd);
+            self.buf.unsplit(spare);
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Option<T> {
+        self.fd
+    }
+}
+
+fn into_string(line: BytesMut) -> io::Result<String> {
+    String::from_utf8(line.to_vec())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+bitflags::bitflags!{
+    /// Flags for [`splice`] and [`tee`], see `splice(2)`.
+    pub struct SpliceFlags: u32 {
+        const MOVE = libc::SPLICE_F_MOVE;
+        const NONBLOCK = libc::SPLICE_F_NONBLOCK;
+        const MORE = libc::SPLICE_F_MORE;
+        const GIFT = libc::SPLICE_F_GIFT;
+    }
+}
+
+/// Move data between two fds, one of which must be a pipe.
+///
+/// The offset of a pipe must be `None`, offsets past `i64::MAX` fail with `InvalidInput`.
+pub async fn splice<H: Handle, I: TrustedAsRawFd, O: TrustedAsRawFd>(
+    handle: H,
+    fd_in: &mut Option<I>,
+    off_in: Option<u64>,
+    fd_out: &mut Option<O>,
+    off_out: Option<u64>,
+    len: u32,
+    flags: SpliceFlags
+)
+    -> io::Result<(I, O, usize)>
+{
+    splice_with(handle, fd_in, off_in, fd_out, off_out, len, flags, &ActionOptions::new()).await
+}
+
+/// Like [`splice`], with `options` applied to its entry.
+#[allow(clippy::too_many_arguments)]
+pub async fn splice_with<H: Handle, I: TrustedAsRawFd, O: TrustedAsRawFd>(
+    handle: H,
+    fd_in: &mut Option<I>,
+    off_in: Option<u64>,
+    fd_out: &mut Option<O>,
+    off_out: Option<u64>,
+    len: u32,
+    flags: SpliceFlags,
+    options: &ActionOptions
+)
+    -> io::Result<(I, O, usize)>
+{
+    let off_in = splice_offset(off_in, 0)?;
+    let off_out = splice_offset(off_out, 0)?;
+
+    let (fd_in2, fd_out2) = match (fd_in.take(), fd_out.take()) {
+        (Some(fd_in2), Some(fd_out2)) => (fd_in2, fd_out2),
+        (fd_in2, fd_out2) => {
+            *fd_in = fd_in2;
+            *fd_out = fd_out2;
+            return Err(not_found());
+        }
+    };
+
+    let splice_e = opcode::Splice::new(
+        types::Fd(fd_in2.as_raw_fd()),
+        off_in,
+        types::Fd(fd_out2.as_raw_fd()),
+        off_out,
+        len
+    )
+        .flags(flags.bits())
+        .build();
+
+    let ((fd_in2, fd_out2), cqe) = unsafe {
+        action(handle, (fd_in2, fd_out2), options.apply(splice_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok((fd_in2, fd_out2, ret as _))
+    } else {
+        *fd_in = Some(fd_in2);
+        *fd_out = Some(fd_out2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Duplicate data from one pipe to another without consuming it.
+pub async fn tee<H: Handle, I: TrustedAsRawFd, O: TrustedAsRawFd>(
+    handle: H,
+    fd_in: &mut Option<I>,
+    fd_out: &mut Option<O>,
+    len: u32,
+    flags: SpliceFlags
+)
+    -> io::Result<(I, O, usize)>
+{
+    tee_with(handle, fd_in, fd_out, len, flags, &ActionOptions::new()).await
+}
+
+/// Like [`tee`], with `options` applied to its entry.
+pub async fn tee_with<H: Handle, I: TrustedAsRawFd, O: TrustedAsRawFd>(
+    handle: H,
+    fd_in: &mut Option<I>,
+    fd_out: &mut Option<O>,
+    len: u32,
+    flags: SpliceFlags,
+    options: &ActionOptions
+)
+    -> io::Result<(I, O, usize)>
+{
+    let (fd_in2, fd_out2) = match (fd_in.take(), fd_out.take()) {
+        (Some(fd_in2), Some(fd_out2)) => (fd_in2, fd_out2),
+        (fd_in2, fd_out2) => {
+            *fd_in = fd_in2;
+            *fd_out = fd_out2;
+            return Err(not_found());
+        }
+    };
+
+    let tee_e = opcode::Tee::new(
+        types::Fd(fd_in2.as_raw_fd()),
+        types::Fd(fd_out2.as_raw_fd()),
+        len
+    )
+        .flags(flags.bits())
+        .build();
+
+    let ((fd_in2, fd_out2), cqe) = unsafe {
+        action(handle, (fd_in2, fd_out2), options.apply(tee_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok((fd_in2, fd_out2, ret as _))
+    } else {
+        *fd_in = Some(fd_in2);
+        *fd_out = Some(fd_out2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// Zero-copy transfer of `range` of a file to a socket, like `sendfile(2)`.
+///
+/// The data goes through an internal pipe, using linked splice pairs.
+/// An unbounded range stops at the end of file, a range past `i64::MAX`
+/// fails with `InvalidInput`. Returns the number of bytes transferred.
+pub async fn send_file<H: Handle, I: TrustedAsRawFd, O: TrustedAsRawFd>(
+    handle: H,
+    file: &mut Option<I>,
+    socket: &mut Option<O>,
+    range: impl RangeBounds<u64>
+)
+    -> io::Result<(I, O, u64)>
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0)
+    };
+    let start = match start {
+        Some(start) if start <= i64::MAX as u64 => start,
+        _ => return Err(invalid_offset())
+    };
+    let len = match range.end_bound() {
+        Bound::Included(&end) => Some(end.checked_add(1).ok_or_else(invalid_offset)?.saturating_sub(start)),
+        Bound::Excluded(&end) => Some(end.saturating_sub(start)),
+        Bound::Unbounded => None
+    };
+
+    splice_all(handle, file, start, socket, None, len).await
+}
+
+struct Pipe {
+    reader: OwnedFd,
+    writer: OwnedFd,
+    size: u32
+}
+
+impl Pipe {
+    fn new() -> io::Result<Pipe> {
+        let mut fds = [0; 2];
+
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let (reader, writer) = unsafe {
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        };
+
+        // A larger pipe means fewer round trips, but it is only a hint.
+        let size = unsafe {
+            libc::fcntl(writer.as_raw_fd(), libc::F_SETPIPE_SZ, 1 << 20);
+            libc::fcntl(writer.as_raw_fd(), libc::F_GETPIPE_SZ)
+        };
+        let size = if size > 0 { size as u32 } else { 1 << 16 };
+
+        Ok(Pipe { reader, writer, size })
+    }
+}
+
+/// Move up to `len` bytes from `fd_in` to `fd_out` through a pipe,
+/// or until end of file if `len` is `None`.
+///
+/// Each chunk is a splice into the pipe linked to a splice out of it,
+/// so that it takes a single submission. A short splice in breaks the link,
+/// what it moved is then drained from the pipe like after a short splice out.
+pub(crate) async fn splice_all<H: Handle, I: AsRawFd + 'static, O: AsRawFd + 'static>(
+    handle: H,
+    fd_in: &mut Option<I>,
+    off_in: u64,
+    fd_out: &mut Option<O>,
+    off_out: Option<u64>,
+    len: Option<u64>
+)
+    -> io::Result<(I, O, u64)>
+{
+    let pipe = Rc::new(Pipe::new()?);
+    let mut total = 0;
+
+    loop {
+        let want = match len {
+            Some(len) => cmp::min(len - total, pipe.size as u64) as u32,
+            None => pipe.size
+        };
+
+        if want == 0 {
+            break
+        }
+
+        let in_off = splice_offset(Some(off_in), total)?;
+        let out_off = splice_offset(off_out, total)?;
+
+        let (fd_in2, fd_out2) = match (fd_in.take(), fd_out.take()) {
+            (Some(fd_in2), Some(fd_out2)) => (fd_in2, fd_out2),
+            (fd_in2, fd_out2) => {
+                *fd_in = fd_in2;
+                *fd_out = fd_out2;
+                return Err(not_found());
+            }
+        };
+
+        let in_e = opcode::Splice::new(
+            types::Fd(fd_in2.as_raw_fd()),
+            in_off,
+            types::Fd(pipe.writer.as_raw_fd()),
+            -1,
+            want
+        )
+            .build();
+        let out_e = opcode::Splice::new(
+            types::Fd(pipe.reader.as_raw_fd()),
+            -1,
+            types::Fd(fd_out2.as_raw_fd()),
+            out_off,
+            want
+        )
+            .build();
+
+        let chain = Chain::new().link(in_e).link(out_e);
+        let ((fd_in2, fd_out2, _), cqes) = match unsafe { chain.submit(&handle, (fd_in2, fd_out2, pipe.clone())) } {
+            Ok(action) => action.await,
+            Err(err) => {
+                let (err, (fd_in2, fd_out2, _)) = err.into_inner();
+                *fd_in = Some(fd_in2);
+                *fd_out = Some(fd_out2);
+                return Err(err);
+            }
+        };
+        *fd_in = Some(fd_in2);
+        *fd_out = Some(fd_out2);
+
+        let n = match cqes[0].result() {
+            0 => break,
+            n if n > 0 => n as u32,
+            n => return Err(io::Error::from_raw_os_error(-n))
+        };
+        let mut sent = match cqes[1].result() {
+            ret if ret >= 0 => ret as u32,
+
+            // The link was broken by a short splice in.
+            ret if ret == -libc::ECANCELED => 0,
+            ret => return Err(io::Error::from_raw_os_error(-ret))
+        };
+
+        while sent < n {
+            let off = splice_offset(off_out, total + sent as u64)?;
+
+            match splice_out(&handle, fd_out, &pipe, off, n - sent).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                ret => sent += ret
+            }
+        }
+
+        total += n as u64;
+    }
+
+    match (fd_in.take(), fd_out.take()) {
+        (Some(fd_in2), Some(fd_out2)) => Ok((fd_in2, fd_out2, total)),
+        _ => Err(not_found())
+    }
+}
+
+async fn splice_out<H: Handle, O: AsRawFd + 'static>(
+    handle: H,
+    fd_out: &mut Option<O>,
+    pipe: &Rc<Pipe>,
+    off_out: i64,
+    len: u32
+)
+    -> io::Result<u32>
+{
+    let fd_out2 = match fd_out.take() {
+        Some(fd_out2) => fd_out2,
+        None => return Err(not_found())
+    };
+
+    let out_e = opcode::Splice::new(
+        types::Fd(pipe.reader.as_raw_fd()),
+        -1,
+        types::Fd(fd_out2.as_raw_fd()),
+        off_out,
+        len
+    )
+        .build();
+
+    let ((fd_out2, _), cqe) = match unsafe { action(handle, (fd_out2, pipe.clone()), out_e) } {
+        Ok(action) => action.await,
+        Err(err) => {
+            let (err, (fd_out2, _)) = err.into_inner();
+            *fd_out = Some(fd_out2);
+            return Err(err);
+        }
+    };
+    *fd_out = Some(fd_out2);
+
+    match cqe.result() {
+        ret if ret >= 0 => Ok(ret as u32),
+        ret => Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// The offset `advance` bytes past `base` as the kernel takes it,
+/// where -1 means the current position of the fd.
+fn splice_offset(base: Option<u64>, advance: u64) -> io::Result<i64> {
+    match base {
+        Some(base) => base.checked_add(advance)
+            .and_then(|offset| i64::try_from(offset).ok())
+            .ok_or_else(invalid_offset),
+        None => Ok(-1)
+    }
+}
+
+#[cold]
+fn invalid_offset() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Offset is out of range"
+    )
+}
+
 #[cold]
-fn not_found() -> io::Error {
+pub(crate) fn not_found() -> io::Error {
     io::Error::new(
         io::ErrorKind::NotFound,
         "No available fd was found"
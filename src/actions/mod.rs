@@ -1,15 +1,23 @@
 pub mod io;
 pub mod fs;
 pub mod time;
+pub mod options;
+pub mod poll;
 
+use std::{ fmt, ptr };
 use std::pin::Pin;
+use std::time::Duration;
 use std::future::Future;
-use std::mem::MaybeUninit;
+use std::mem::{ ManuallyDrop, MaybeUninit };
 use std::task::{ Context, Poll };
-use io_uring::{ squeue, cqueue };
+use io_uring::{ types, opcode, squeue, cqueue };
 use pin_project_lite::pin_project;
+use futures_core::Stream;
 use crate::ticket::{ Ticket, TicketFuture };
+use crate::ticket::multishot::{ self, MultiTicket, MultiTicketStream };
 use crate::handle::Handle;
+use crate::actions::options::ActionOptions;
+use crate::MULTISHOT_TAG;
 
 
 pin_project!{
@@ -20,6 +28,68 @@ pin_project!{
     }
 }
 
+pin_project!{
+    /// Multishot action, yields every completion of the request.
+    ///
+    /// The held value is only released after the final completion.
+    pub struct MultiAction<T: 'static> {
+        hold: Option<T>,
+        #[pin]
+        ticket: MultiTicketStream
+    }
+
+    impl<T: 'static> PinnedDrop for MultiAction<T> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+
+            // The kernel may still use it.
+            if !this.ticket.is_done() {
+                std::mem::forget(this.hold.take());
+            }
+        }
+    }
+}
+
+pin_project!{
+    /// Action with a linked timeout, see [`with_timeout`].
+    pub struct TimeoutAction<T: 'static> {
+        hold: MaybeUninit<(T, Box<types::Timespec>)>,
+        entry: Option<cqueue::Entry>,
+        timeout: Option<cqueue::Entry>,
+        #[pin]
+        ticket: TicketFuture,
+        #[pin]
+        timeout_ticket: TicketFuture
+    }
+}
+
+/// An ordered chain of linked entries, submitted together.
+///
+/// Each entry only starts after the previous one completed. With a soft link
+/// a failed or short entry breaks the rest of the chain, and every following
+/// entry completes with `-ECANCELED`. A hard link keeps going regardless
+/// of the result of the previous entry.
+#[derive(Default)]
+pub struct Chain {
+    entries: Vec<(squeue::Entry, squeue::Flags)>
+}
+
+pin_project!{
+    /// Action for a linked chain, see [`Chain::submit`].
+    ///
+    /// Resolves once every entry has completed.
+    pub struct ChainAction<T: 'static> {
+        hold: MaybeUninit<T>,
+        entries: Vec<Option<cqueue::Entry>>,
+        tickets: Vec<TicketFuture>
+    }
+}
+
+/// The linked timeout expired before the action completed.
+pub struct TimedOut<T> {
+    value: T
+}
+
 pub struct PushError<T> {
     error: std::io::Error,
     value: T
@@ -49,6 +119,152 @@ pub unsafe fn action<H: Handle, T: 'static>(handle: H, value: T, entry: squeue::
     }
 }
 
+/// Multishot action helper function
+///
+/// # Safety
+///
+/// Must ensure that the io_uring submission entry parameter is valid,
+/// and that it is a multishot request.
+pub unsafe fn action_multi<H: Handle, T: 'static>(handle: H, value: T, entry: squeue::Entry)
+    -> Result<MultiAction<T>, PushError<T>>
+{
+    let (tx, ticket) = multishot::channel();
+    let tx_ptr = tx.into_raw();
+    let entry = entry.user_data(tx_ptr.as_ptr() as u64 | MULTISHOT_TAG);
+
+    match handle.push(&entry) {
+        Ok(()) => Ok(MultiAction { hold: Some(value), ticket }),
+        Err(error) => {
+            MultiTicket::from_raw(tx_ptr);
+            Err(PushError { error, value })
+        }
+    }
+}
+
+/// Action helper function, which is cancelled if it does not complete within `dur`.
+///
+/// The entry is pushed with `IOSQE_IO_LINK` followed by a `LinkTimeout`,
+/// and resolves after both completions arrived.
+///
+/// # Safety
+///
+/// Must ensure that the io_uring submission entry parameter is valid.
+pub unsafe fn with_timeout<H: Handle, T: 'static>(
+    handle: H,
+    value: T,
+    entry: squeue::Entry,
+    dur: Duration
+)
+    -> Result<TimeoutAction<T>, PushError<T>>
+{
+    let (tx, ticket) = Ticket::new();
+    let (timeout_tx, timeout_ticket) = Ticket::new();
+    let tx_ptr = tx.into_raw();
+    let timeout_tx_ptr = timeout_tx.into_raw();
+
+    let timespec = Box::new(types::Timespec::new()
+        .sec(dur.as_secs())
+        .nsec(dur.subsec_nanos()));
+
+    let entry = entry
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(tx_ptr.as_ptr() as _);
+    let timeout_e = opcode::LinkTimeout::new(&*timespec)
+        .build()
+        .user_data(timeout_tx_ptr.as_ptr() as _);
+
+    match handle.push_multiple(&[entry, timeout_e]) {
+        Ok(()) => Ok(TimeoutAction {
+            hold: MaybeUninit::new((value, timespec)),
+            entry: None,
+            timeout: None,
+            ticket,
+            timeout_ticket
+        }),
+        Err(error) => {
+            Ticket::from_raw(tx_ptr);
+            Ticket::from_raw(timeout_tx_ptr);
+            Err(PushError { error, value })
+        }
+    }
+}
+
+impl Chain {
+    #[inline]
+    pub fn new() -> Chain {
+        Chain::default()
+    }
+
+    /// Append an entry that only starts if the previous one succeeded.
+    pub fn link(mut self, entry: squeue::Entry) -> Chain {
+        self.entries.push((entry, squeue::Flags::IO_LINK));
+        self
+    }
+
+    /// Append an entry that starts after the previous one whatever its result.
+    pub fn hard_link(mut self, entry: squeue::Entry) -> Chain {
+        self.entries.push((entry, squeue::Flags::IO_HARDLINK));
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Push the whole chain at once, so that it is never split across submissions.
+    ///
+    /// # Safety
+    ///
+    /// Must ensure that every io_uring submission entry in the chain is valid.
+    pub unsafe fn submit<H: Handle, T: 'static>(self, handle: H, value: T)
+        -> Result<ChainAction<T>, PushError<T>>
+    {
+        let len = self.entries.len();
+        let mut entries = Vec::with_capacity(len);
+        let mut txs = Vec::with_capacity(len);
+        let mut tickets = Vec::with_capacity(len);
+
+        // The link flag goes on the entry before, it says that the next one depends on it.
+        let links = self.entries.iter()
+            .skip(1)
+            .map(|(_, flags)| *flags)
+            .collect::<Vec<_>>();
+
+        for (i, (entry, _)) in self.entries.into_iter().enumerate() {
+            let (tx, ticket) = Ticket::new();
+            let tx_ptr = tx.into_raw();
+            let entry = match links.get(i) {
+                Some(&flags) => entry.flags(flags),
+                None => entry
+            };
+
+            entries.push(entry.user_data(tx_ptr.as_ptr() as _));
+            txs.push(tx_ptr);
+            tickets.push(ticket);
+        }
+
+        match handle.push_multiple(&entries) {
+            Ok(()) => Ok(ChainAction {
+                hold: MaybeUninit::new(value),
+                entries: vec![None; len],
+                tickets
+            }),
+            Err(error) => {
+                for tx_ptr in txs {
+                    Ticket::from_raw(tx_ptr);
+                }
+                Err(PushError { error, value })
+            }
+        }
+    }
+}
+
 impl<T: 'static> Future for Action<T> {
     type Output = (T, cqueue::Entry);
 
@@ -66,6 +282,151 @@ impl<T: 'static> Future for Action<T> {
     }
 }
 
+impl<T: 'static> Future for TimeoutAction<T> {
+    type Output = Result<(T, cqueue::Entry), TimedOut<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if this.entry.is_none() {
+            if let Poll::Ready(entry) = this.ticket.poll(cx) {
+                *this.entry = Some(entry);
+            }
+        }
+
+        if this.timeout.is_none() {
+            if let Poll::Ready(entry) = this.timeout_ticket.poll(cx) {
+                *this.timeout = Some(entry);
+            }
+        }
+
+        match (this.entry.take(), this.timeout.take()) {
+            (Some(entry), Some(timeout)) => {
+                let (value, _) = unsafe { this.hold.as_ptr().read() };
+
+                // A request that was already running in io-wq is interrupted
+                // instead of cancelled.
+                let cancelled = entry.result() == -libc::ECANCELED
+                    || entry.result() == -libc::EINTR;
+
+                if timeout.result() == -libc::ETIME && cancelled {
+                    Poll::Ready(Err(TimedOut { value }))
+                } else {
+                    Poll::Ready(Ok((value, entry)))
+                }
+            },
+            (entry, timeout) => {
+                *this.entry = entry;
+                *this.timeout = timeout;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: 'static> Future for ChainAction<T> {
+    type Output = (T, Vec<cqueue::Entry>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut done = true;
+
+        for (entry, ticket) in this.entries.iter_mut().zip(this.tickets.iter_mut()) {
+            if entry.is_none() {
+                match Pin::new(ticket).poll(cx) {
+                    Poll::Ready(cqe) => *entry = Some(cqe),
+                    Poll::Pending => done = false
+                }
+            }
+        }
+
+        if done {
+            let value = unsafe { this.hold.as_ptr().read() };
+            let entries = this.entries.drain(..).flatten().collect();
+            Poll::Ready((value, entries))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T: 'static> Action<T> {
+    #[inline]
+    pub(crate) fn is_completed(&self) -> bool {
+        self.ticket.is_closed()
+    }
+
+    /// Takes the held value back if the request has completed,
+    /// otherwise gives the action back.
+    ///
+    /// Dropping an action leaks its value, even after the completion arrived.
+    pub(crate) fn into_completed(self) -> Result<T, Action<T>> {
+        if !self.is_completed() {
+            return Err(self);
+        }
+
+        let this = ManuallyDrop::new(self);
+
+        unsafe {
+            drop(ptr::read(&this.ticket));
+            Ok(this.hold.as_ptr().read())
+        }
+    }
+
+    #[inline]
+    pub(crate) fn user_data(&self) -> u64 {
+        self.ticket.as_ptr().as_ptr() as _
+    }
+}
+
+impl<T: 'static> Stream for MultiAction<T> {
+    type Item = cqueue::Entry;
+
+    #[inline]
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().ticket.poll_next(cx)
+    }
+}
+
+impl<T: 'static> MultiAction<T> {
+    /// Takes the held value back once the request has finished.
+    pub fn into_inner(mut self) -> Option<T> {
+        if self.ticket.is_done() {
+            self.hold.take()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_done(&self) -> bool {
+        self.ticket.is_done()
+    }
+
+    #[inline]
+    pub(crate) fn user_data(&self) -> u64 {
+        self.ticket.as_ptr().as_ptr() as u64 | MULTISHOT_TAG
+    }
+}
+
+impl<T> fmt::Debug for TimedOut<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedOut").finish_non_exhaustive()
+    }
+}
+
+impl<T> TimedOut<T> {
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    #[inline]
+    pub fn into_error(self) -> std::io::Error {
+        std::io::ErrorKind::TimedOut.into()
+    }
+}
+
 impl<T> PushError<T> {
     #[inline]
     pub fn into_inner(self) -> (std::io::Error, T) {
@@ -98,12 +459,17 @@ pub fn cancel<H: Handle, T: 'static>(handle: H, action: Action<T>) -> std::io::R
 }
 
 pub async fn nop<H: Handle>(handle: H) -> std::io::Result<()> {
+    nop_with(handle, &ActionOptions::new()).await
+}
+
+/// Like [`nop`], with `options` applied to its entry.
+pub async fn nop_with<H: Handle>(handle: H, options: &ActionOptions) -> std::io::Result<()> {
     use io_uring::opcode;
 
     let nop_e = opcode::Nop::new().build();
 
     unsafe {
-        action(handle, (), nop_e)
+        action(handle, (), options.apply(nop_e))
             .map_err(PushError::into_error)?.await;
     }
 
@@ -0,0 +1,110 @@
+use io_uring::{ opcode, squeue };
+use crate::sqe::RawEntry;
+
+
+bitflags::bitflags!{
+    /// Per-request flags for reads and writes, see `preadv2(2)`.
+    pub struct RwFlags: u32 {
+        const HIPRI = libc::RWF_HIPRI as _;
+        const DSYNC = libc::RWF_DSYNC as _;
+        const SYNC = libc::RWF_SYNC as _;
+        const NOWAIT = libc::RWF_NOWAIT as _;
+        const APPEND = libc::RWF_APPEND as _;
+    }
+}
+
+/// Options applied to the entry of an action.
+///
+/// The actions that push a single entry have a `_with` variant that takes
+/// them, like [`read_buf_with`](crate::actions::io::read_buf_with). They only
+/// apply to that entry, not to a cancel or a linked timeout pushed for it.
+/// Entries built by hand get them through [`ActionOptions::apply`].
+///
+/// `ioprio` and `rw_flags` only apply to read and write opcodes,
+/// other opcodes use these fields for their own flags.
+#[derive(Clone, Debug)]
+pub struct ActionOptions {
+    flags: squeue::Flags,
+    personality: Option<u16>,
+    ioprio: Option<u16>,
+    rw_flags: RwFlags
+}
+
+impl ActionOptions {
+    pub const fn new() -> ActionOptions {
+        ActionOptions {
+            flags: squeue::Flags::empty(),
+            personality: None,
+            ioprio: None,
+            rw_flags: RwFlags::empty()
+        }
+    }
+
+    /// Set `IOSQE_IO_DRAIN`, the entry starts only after all previous entries completed.
+    ///
+    /// The proactor completes the read it keeps on its eventfd while parked,
+    /// so the entry does not wait for a wake up. A pending timer wheel timeout
+    /// is still waited for.
+    pub fn drain(&mut self, drain: bool) -> &mut Self {
+        self.flags.set(squeue::Flags::IO_DRAIN, drain);
+        self
+    }
+
+    /// Set `IOSQE_ASYNC`, the entry is always punted to an io worker.
+    pub fn force_async(&mut self, force: bool) -> &mut Self {
+        self.flags.set(squeue::Flags::ASYNC, force);
+        self
+    }
+
+    /// Issue the request with the credentials registered as `personality`.
+    pub fn personality(&mut self, personality: u16) -> &mut Self {
+        self.personality = Some(personality);
+        self
+    }
+
+    /// I/O priority, see `ioprio_set(2)`.
+    pub fn ioprio(&mut self, ioprio: u16) -> &mut Self {
+        self.ioprio = Some(ioprio);
+        self
+    }
+
+    pub fn rw_flags(&mut self, flags: RwFlags) -> &mut Self {
+        self.rw_flags = flags;
+        self
+    }
+
+    /// Apply the options to an entry.
+    pub fn apply(&self, entry: squeue::Entry) -> squeue::Entry {
+        let mut entry = RawEntry::from_entry(entry.flags(self.flags));
+
+        if let Some(personality) = self.personality {
+            entry.personality = personality;
+        }
+
+        if is_rw(entry.opcode) {
+            if let Some(ioprio) = self.ioprio {
+                entry.ioprio = ioprio;
+            }
+
+            entry.op_flags |= self.rw_flags.bits();
+        }
+
+        entry.build()
+    }
+}
+
+impl Default for ActionOptions {
+    #[inline]
+    fn default() -> ActionOptions {
+        ActionOptions::new()
+    }
+}
+
+fn is_rw(code: u8) -> bool {
+    code == opcode::Read::CODE
+        || code == opcode::Write::CODE
+        || code == opcode::Readv::CODE
+        || code == opcode::Writev::CODE
+        || code == opcode::ReadFixed::CODE
+        || code == opcode::WriteFixed::CODE
+}
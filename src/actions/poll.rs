@@ -0,0 +1,195 @@
+use std::io;
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{ Context, Poll };
+use futures_core::Stream;
+use io_uring::{ types, opcode };
+use crate::EMPTY_TOKEN;
+use crate::handle::Handle;
+use crate::actions::io::{ TrustedAsRawFd, not_found };
+use crate::actions::options::ActionOptions;
+use crate::actions::{ action, action_multi, Action, MultiAction, PushError };
+
+
+bitflags::bitflags!{
+    /// Poll events, see `poll(2)`.
+    pub struct PollFlags: u32 {
+        const IN = libc::POLLIN as _;
+        const PRI = libc::POLLPRI as _;
+        const OUT = libc::POLLOUT as _;
+        const ERR = libc::POLLERR as _;
+        const HUP = libc::POLLHUP as _;
+        const NVAL = libc::POLLNVAL as _;
+        const RDHUP = libc::POLLRDHUP as _;
+    }
+}
+
+/// Wait until the fd is ready for any of `events`, returns the ready events.
+///
+/// `ERR` and `HUP` are always reported, even if not requested.
+pub async fn poll<H: Handle, T: TrustedAsRawFd>(handle: H, fd: &mut Option<T>, events: PollFlags)
+    -> io::Result<(T, PollFlags)>
+{
+    poll_with(handle, fd, events, &ActionOptions::new()).await
+}
+
+/// Like [`poll`], with `options` applied to its entry.
+pub async fn poll_with<H: Handle, T: TrustedAsRawFd>(
+    handle: H,
+    fd: &mut Option<T>,
+    events: PollFlags,
+    options: &ActionOptions
+)
+    -> io::Result<(T, PollFlags)>
+{
+    let fd2 = match fd.take() {
+        Some(fd) => fd,
+        None => return Err(not_found())
+    };
+
+    let poll_e = opcode::PollAdd::new(types::Fd(fd2.as_raw_fd()), events.bits())
+        .build();
+
+    let (fd2, cqe) = unsafe {
+        action(handle, fd2, options.apply(poll_e))
+            .map_err(PushError::into_error)?.await
+    };
+
+    let ret = cqe.result();
+    if ret >= 0 {
+        Ok((fd2, PollFlags::from_bits_truncate(ret as _)))
+    } else {
+        *fd = Some(fd2);
+        Err(io::Error::from_raw_os_error(-ret))
+    }
+}
+
+/// A stream of readiness events, see [`poll_multi`].
+pub struct PollStream<H: Handle, T: TrustedAsRawFd> {
+    handle: H,
+    fd: T,
+    events: PollFlags,
+    state: PollState
+}
+
+enum PollState {
+    Idle,
+    Multishot(MultiAction<()>),
+    Oneshot(Action<()>)
+}
+
+/// Yield readiness events until the stream is dropped.
+///
+/// Uses a multishot poll where the kernel supports it, otherwise
+/// a oneshot poll is armed again after every event.
+///
+/// Like edge-triggered epoll, an event is posted when the fd gets woken up,
+/// not while it stays ready, so consume what is available before waiting again.
+pub fn poll_multi<H: Handle, T: TrustedAsRawFd>(handle: H, fd: T, events: PollFlags) -> PollStream<H, T> {
+    PollStream {
+        handle,
+        fd,
+        events,
+        state: PollState::Idle
+    }
+}
+
+impl<H: Handle, T: TrustedAsRawFd> PollStream<H, T> {
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.fd
+    }
+
+    fn arm_oneshot(&mut self) -> io::Result<()> {
+        let poll_e = opcode::PollAdd::new(types::Fd(self.fd.as_raw_fd()), self.events.bits())
+            .build();
+
+        let action = unsafe {
+            action(&self.handle, (), poll_e)
+                .map_err(PushError::into_error)?
+        };
+        self.state = PollState::Oneshot(action);
+
+        Ok(())
+    }
+}
+
+impl<H: Handle + Unpin, T: TrustedAsRawFd + Unpin> Stream for PollStream<H, T> {
+    type Item = io::Result<PollFlags>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                PollState::Idle => {
+                    let poll_e = opcode::PollAdd::new(types::Fd(this.fd.as_raw_fd()), this.events.bits())
+                        .multi(true)
+                        .build();
+
+                    let action = match unsafe { action_multi(&this.handle, (), poll_e) } {
+                        Ok(action) => action,
+                        Err(err) => return Poll::Ready(Some(Err(err.into_error())))
+                    };
+
+                    this.state = PollState::Multishot(action);
+                },
+                PollState::Multishot(action) => {
+                    let cqe = match futures_core::ready!(Pin::new(action).poll_next(cx)) {
+                        Some(cqe) => cqe,
+                        None => {
+                            // The kernel may end a multishot poll, e.g. under memory pressure.
+                            this.state = PollState::Idle;
+                            continue
+                        }
+                    };
+
+                    match cqe.result() {
+                        ret if ret >= 0 =>
+                            return Poll::Ready(Some(Ok(PollFlags::from_bits_truncate(ret as _)))),
+
+                        // Older kernels do not know about multishot polls.
+                        ret if ret == -libc::EINVAL => if let Err(err) = this.arm_oneshot() {
+                            return Poll::Ready(Some(Err(err)));
+                        },
+                        ret => return Poll::Ready(Some(Err(io::Error::from_raw_os_error(-ret))))
+                    }
+                },
+                PollState::Oneshot(action) => {
+                    let (_, cqe) = futures_core::ready!(Pin::new(action).poll(cx));
+
+                    let ret = cqe.result();
+                    let ret = if ret >= 0 {
+                        this.arm_oneshot().map(|_| PollFlags::from_bits_truncate(ret as _))
+                    } else {
+                        Err(io::Error::from_raw_os_error(-ret))
+                    };
+
+                    return Poll::Ready(Some(ret));
+                }
+            }
+        }
+    }
+}
+
+impl<H: Handle, T: TrustedAsRawFd> Drop for PollStream<H, T> {
+    fn drop(&mut self) {
+        let user_data = match &self.state {
+            PollState::Multishot(action) if !action.is_done() => action.user_data(),
+            PollState::Oneshot(action) if !action.is_completed() => action.user_data(),
+            _ => return
+        };
+
+        let _ = poll_remove(&self.handle, user_data);
+    }
+}
+
+pub(crate) fn poll_remove<H: Handle>(handle: H, user_data: u64) -> io::Result<()> {
+    let remove_e = opcode::PollRemove::new(user_data)
+        .build()
+        .user_data(EMPTY_TOKEN);
+
+    unsafe {
+        handle.push(&remove_e)
+    }
+}
@@ -0,0 +1,165 @@
+//! Thread pool for syscalls that the running kernel cannot do through io_uring.
+
+use std::{ io, mem, ptr, thread };
+use std::pin::Pin;
+use std::future::Future;
+use std::time::Duration;
+use std::sync::{ Mutex, Condvar };
+use std::collections::VecDeque;
+use std::panic::{ self, AssertUnwindSafe };
+use std::task::{ Context, Poll };
+use crate::ticket::oneshot;
+
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+    state: Mutex<State>,
+    cond: Condvar
+}
+
+struct State {
+    queue: VecDeque<Job>,
+    idle: usize,
+    threads: usize
+}
+
+pub struct Blocking<T> {
+    rx: oneshot::Receiver<io::Result<T>>
+}
+
+const MAX_THREADS: usize = 16;
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+static POOL: Pool = Pool {
+    state: Mutex::new(State {
+        queue: VecDeque::new(),
+        idle: 0,
+        threads: 0
+    }),
+    cond: Condvar::new()
+};
+
+pub fn spawn<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static
+{
+    let (tx, rx) = oneshot::channel();
+    let job = Box::new(move || {
+        let _ = tx.send(f());
+    });
+
+    let mut state = POOL.state.lock().unwrap_or_else(|err| err.into_inner());
+    state.queue.push_back(job);
+
+    if state.idle > 0 {
+        POOL.cond.notify_one();
+    } else if state.threads < MAX_THREADS {
+        state.threads += 1;
+
+        let ret = thread::Builder::new()
+            .name("ritsu-blocking".into())
+            .spawn(worker);
+
+        if ret.is_err() {
+            state.threads -= 1;
+
+            // No worker can take the job, so run it here instead of losing it.
+            if state.threads == 0 {
+                let job = state.queue.pop_back();
+                drop(state);
+
+                if let Some(job) = job {
+                    job();
+                }
+            }
+        }
+    }
+
+    Blocking { rx }
+}
+
+fn worker() {
+    block_signals();
+
+    let mut state = POOL.state.lock().unwrap_or_else(|err| err.into_inner());
+
+    loop {
+        if let Some(job) = state.queue.pop_front() {
+            drop(state);
+
+            // The sender is dropped on panic, the receiver will see an error.
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+
+            state = POOL.state.lock().unwrap_or_else(|err| err.into_inner());
+            continue
+        }
+
+        state.idle += 1;
+        let (state2, timeout) = POOL.cond.wait_timeout(state, KEEP_ALIVE)
+            .unwrap_or_else(|err| err.into_inner());
+        state = state2;
+        state.idle -= 1;
+
+        if timeout.timed_out() && state.queue.is_empty() {
+            state.threads -= 1;
+            return
+        }
+    }
+}
+
+/// Leave asynchronous signals to the other threads,
+/// a worker may have been spawned before they were blocked for a signalfd.
+fn block_signals() {
+    unsafe {
+        let mut set = mem::zeroed::<libc::sigset_t>();
+        libc::sigfillset(&mut set);
+
+        // These are sent to the thread that caused them.
+        for &signal in &[libc::SIGSEGV, libc::SIGBUS, libc::SIGFPE, libc::SIGILL, libc::SIGTRAP] {
+            libc::sigdelset(&mut set, signal);
+        }
+
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+    }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = io::Result<T>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(Some(ret)) => Poll::Ready(ret),
+            Poll::Ready(None) => Poll::Ready(Err(io::Error::other("blocking task panicked"))),
+            Poll::Pending => Poll::Pending
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn workers_block_signals() {
+        let (tx, rx) = mpsc::channel();
+
+        // The job runs even if its future is dropped.
+        drop(spawn(move || {
+            let mut set = unsafe { mem::zeroed::<libc::sigset_t>() };
+            unsafe {
+                libc::pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut set);
+            }
+
+            let is_blocked = |signal| unsafe { libc::sigismember(&set, signal) == 1 };
+            let _ = tx.send((is_blocked(libc::SIGINT), is_blocked(libc::SIGTERM), is_blocked(libc::SIGSEGV)));
+            Ok(())
+        }));
+
+        assert_eq!(rx.recv().unwrap(), (true, true, false));
+    }
+}
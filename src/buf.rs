@@ -0,0 +1,347 @@
+//! Owned buffers that can be handed to the kernel.
+//!
+//! An action moves its buffer into the action while the kernel uses it,
+//! so the memory must not move along with the value. These traits promise that.
+
+use std::cmp;
+use std::ops::{ Bound, RangeBounds };
+use bytes::{ Bytes, BytesMut };
+
+
+/// A buffer the kernel can read from.
+///
+/// # Safety
+///
+/// The memory behind `stable_ptr` must stay valid and must not move
+/// when the value is moved, until it is dropped or mutably accessed.
+/// The first `bytes_init` bytes must be initialized.
+pub unsafe trait IoBuf: 'static {
+    fn stable_ptr(&self) -> *const u8;
+
+    /// Number of initialized bytes.
+    fn bytes_init(&self) -> usize;
+
+    /// Total capacity of the buffer.
+    fn bytes_total(&self) -> usize;
+
+    /// Restrict the buffer to a range, bounded by `bytes_total`.
+    fn slice(self, range: impl RangeBounds<usize>) -> Slice<Self>
+    where Self: Sized
+    {
+        let begin = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.bytes_total()
+        };
+
+        assert!(begin <= end, "range start must not be greater than end");
+        assert!(end <= self.bytes_total(), "range end out of bounds");
+
+        Slice { buf: self, begin, end }
+    }
+}
+
+/// A buffer the kernel can write into.
+///
+/// # Safety
+///
+/// Same as [`IoBuf`], and `stable_mut_ptr` must be valid for writes
+/// of `bytes_total` bytes.
+pub unsafe trait IoBufMut: IoBuf {
+    fn stable_mut_ptr(&mut self) -> *mut u8;
+
+    /// Mark the first `pos` bytes as initialized,
+    /// it never shrinks the initialized part.
+    ///
+    /// # Safety
+    ///
+    /// The first `pos` bytes must have been initialized.
+    unsafe fn set_init(&mut self, pos: usize);
+}
+
+/// A buffer registered with the ring, see [`Proactor::register_buffers`](crate::Proactor::register_buffers).
+///
+/// # Safety
+///
+/// The whole buffer must lie in the registered buffer at `buf_index`.
+pub unsafe trait FixedIoBuf: IoBuf {
+    /// The index of the registered buffer that this lies in.
+    fn buf_index(&self) -> u16;
+}
+
+/// A range of a buffer, see [`IoBuf::slice`].
+pub struct Slice<T> {
+    buf: T,
+    begin: usize,
+    end: usize
+}
+
+/// A buffer registered with the ring.
+///
+/// It can be used like any other buffer, or with the fixed read and write actions
+/// that skip mapping the memory for every request.
+pub struct FixedBuf {
+    buf: Box<[u8]>,
+    init: usize,
+    index: u16
+}
+
+impl<T> Slice<T> {
+    #[inline]
+    pub fn begin(&self) -> usize {
+        self.begin
+    }
+
+    #[inline]
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.buf
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+}
+
+impl FixedBuf {
+    #[inline]
+    pub(crate) fn new(buf: Box<[u8]>, index: u16) -> FixedBuf {
+        FixedBuf { buf, init: 0, index }
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.init]
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.init = 0;
+    }
+}
+
+unsafe impl IoBuf for Vec<u8> {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.capacity()
+    }
+}
+
+unsafe impl IoBufMut for Vec<u8> {
+    #[inline]
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    #[inline]
+    unsafe fn set_init(&mut self, pos: usize) {
+        if pos > self.len() {
+            self.set_len(pos);
+        }
+    }
+}
+
+unsafe impl IoBuf for Box<[u8]> {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBufMut for Box<[u8]> {
+    #[inline]
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    #[inline]
+    unsafe fn set_init(&mut self, _pos: usize) {}
+}
+
+unsafe impl IoBuf for BytesMut {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.capacity()
+    }
+}
+
+unsafe impl IoBufMut for BytesMut {
+    #[inline]
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    #[inline]
+    unsafe fn set_init(&mut self, pos: usize) {
+        if pos > self.len() {
+            self.set_len(pos);
+        }
+    }
+}
+
+unsafe impl IoBuf for Bytes {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBuf for &'static [u8] {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBuf for &'static str {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBuf for FixedBuf {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        self.buf.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.init
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+unsafe impl IoBufMut for FixedBuf {
+    #[inline]
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.buf.as_mut_ptr()
+    }
+
+    #[inline]
+    unsafe fn set_init(&mut self, pos: usize) {
+        self.init = cmp::max(self.init, pos);
+    }
+}
+
+unsafe impl FixedIoBuf for FixedBuf {
+    #[inline]
+    fn buf_index(&self) -> u16 {
+        self.index
+    }
+}
+
+unsafe impl<T: IoBuf> IoBuf for Slice<T> {
+    #[inline]
+    fn stable_ptr(&self) -> *const u8 {
+        unsafe { self.buf.stable_ptr().add(self.begin) }
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        cmp::min(self.buf.bytes_init(), self.end).saturating_sub(self.begin)
+    }
+
+    #[inline]
+    fn bytes_total(&self) -> usize {
+        self.end - self.begin
+    }
+}
+
+unsafe impl<T: IoBufMut> IoBufMut for Slice<T> {
+    #[inline]
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        unsafe { self.buf.stable_mut_ptr().add(self.begin) }
+    }
+
+    /// Bytes before the slice that are not initialized stay so,
+    /// and then nothing is marked as initialized.
+    #[inline]
+    unsafe fn set_init(&mut self, pos: usize) {
+        if self.buf.bytes_init() >= self.begin {
+            self.buf.set_init(self.begin + pos);
+        }
+    }
+}
+
+unsafe impl<T: FixedIoBuf> FixedIoBuf for Slice<T> {
+    #[inline]
+    fn buf_index(&self) -> u16 {
+        self.buf.buf_index()
+    }
+}
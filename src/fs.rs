@@ -0,0 +1,258 @@
+use std::{ io, ptr };
+use std::mem::ManuallyDrop;
+use std::fs::File;
+use std::path::{ Path, Component };
+use std::os::unix::io::{ AsRawFd, FromRawFd, IntoRawFd, RawFd, OwnedFd };
+use std::os::unix::fs::PermissionsExt;
+use crate::blocking;
+use crate::handle::Handle;
+use crate::actions::io::splice_all;
+use crate::actions::options::ActionOptions;
+use crate::actions::fs::{
+    self as actions,
+    DirFd, OpenOptions, RenameFlags, ResolveFlags
+};
+
+
+/// A directory handle that all path operations are resolved against.
+///
+/// Paths given to its methods are relative to the directory,
+/// so a `Dir` can be handed out as the only way to reach a subtree.
+///
+/// The directory fd is closed through the ring when `Dir` is dropped.
+pub struct Dir<H: Handle> {
+    handle: H,
+    fd: RawFd,
+    resolve: Option<ResolveFlags>
+}
+
+impl<H: Handle> Dir<H> {
+    /// Open a directory from the process-wide namespace with `O_DIRECTORY | O_PATH`.
+    pub async fn open_ambient(handle: H, path: &Path) -> io::Result<Dir<H>> {
+        let fd = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY | libc::O_PATH)
+            .open(&handle, path)
+            .await?;
+
+        Ok(Dir {
+            handle,
+            fd: fd.into_raw_fd(),
+            resolve: None
+        })
+    }
+
+    /// Restrict path resolution to stay beneath this directory.
+    ///
+    /// With `beneath` enabled, absolute paths, `..` escapes and symlinks
+    /// pointing outside are rejected with `EXDEV`.
+    pub fn set_beneath(&mut self, beneath: bool) {
+        self.resolve = if beneath {
+            Some(ResolveFlags::BENEATH)
+        } else {
+            None
+        };
+    }
+
+    pub fn handle(&self) -> &H {
+        &self.handle
+    }
+
+    /// Open a file relative to this directory.
+    pub async fn open(&self, options: &OpenOptions, path: &Path) -> io::Result<File> {
+        let mut options = options.clone();
+
+        if let Some(resolve) = self.resolve {
+            options.resolve(resolve);
+        }
+
+        options.open_at(&self.handle, self.fd, path, &ActionOptions::new()).await
+    }
+
+    /// Query metadata of a path relative to this directory.
+    ///
+    /// With beneath enabled, a symlink in the last component is not followed.
+    pub async fn statx(&self, path: &Path) -> io::Result<libc::statx> {
+        let (dirfd, name) = self.parent(path).await?;
+        let flags = match dirfd {
+            DirFd::Owned(_) => libc::AT_SYMLINK_NOFOLLOW,
+            _ => 0
+        };
+        let (dirfd, ret) = actions::statx_at(&self.handle, dirfd, name, flags, &ActionOptions::new()).await;
+        self.release(dirfd);
+        ret
+    }
+
+    pub async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let (dirfd, name) = self.parent(path).await?;
+        let (dirfd, ret) = actions::create_dir_at(&self.handle, dirfd, name, &ActionOptions::new()).await;
+        self.release(dirfd);
+        ret
+    }
+
+    pub async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let (dirfd, name) = self.parent(path).await?;
+        let (dirfd, ret) = actions::unlink_at(&self.handle, dirfd, name, 0, &ActionOptions::new()).await;
+        self.release(dirfd);
+        ret
+    }
+
+    pub async fn rename(&self, from: &Path, to: &Path, flags: RenameFlags) -> io::Result<()> {
+        let (from_dirfd, from_name) = self.parent(from).await?;
+        let (to_dirfd, to_name) = match self.parent(to).await {
+            Ok(parent) => parent,
+            Err(err) => {
+                self.release(from_dirfd);
+                return Err(err);
+            }
+        };
+
+        let ((from_dirfd, to_dirfd), ret) = actions::rename_at(
+            &self.handle,
+            (from_dirfd, to_dirfd),
+            from_name,
+            to_name,
+            flags,
+            &ActionOptions::new()
+        ).await;
+        self.release(from_dirfd);
+        self.release(to_dirfd);
+        ret
+    }
+
+    /// Resolve the parent of `path` under the resolve restrictions.
+    ///
+    /// Only `openat2` understands `RESOLVE_*`, so when restricted the parent
+    /// directory is opened with it and the operation targets the last
+    /// component relative to that parent.
+    async fn parent<'a>(&self, path: &'a Path) -> io::Result<(DirFd, &'a Path)> {
+        let resolve = match self.resolve {
+            Some(resolve) => resolve,
+            None => return Ok((DirFd::Borrowed(self.fd), path))
+        };
+
+        let name = match path.components().next_back() {
+            Some(Component::Normal(name)) => Path::new(name),
+            _ => return Err(io::Error::from_raw_os_error(libc::EXDEV))
+        };
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new(".")
+        };
+
+        let fd = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY | libc::O_PATH)
+            .resolve(resolve)
+            .open_at(&self.handle, self.fd, parent, &ActionOptions::new())
+            .await?;
+
+        Ok((DirFd::Owned(fd.into()), name))
+    }
+
+    /// Close a parent opened by [`parent`](Dir::parent) through the ring.
+    #[inline]
+    fn release(&self, dirfd: DirFd) {
+        if let DirFd::Owned(fd) = dirfd {
+            actions::close_detached(&self.handle, fd.into_raw_fd());
+        }
+    }
+
+    /// Close the directory and wait for the result.
+    pub async fn close(self) -> io::Result<()> {
+        let this = ManuallyDrop::new(self);
+        let handle = unsafe { ptr::read(&this.handle) };
+        let fd = unsafe { OwnedFd::from_raw_fd(this.fd) };
+
+        actions::close(handle, fd).await
+    }
+}
+
+impl<H: Handle> AsRawFd for Dir<H> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl<H: Handle> Drop for Dir<H> {
+    fn drop(&mut self) {
+        actions::close_detached(&self.handle, self.fd);
+    }
+}
+
+/// Copy the contents and permissions of one file to another,
+/// returning the number of bytes copied.
+///
+/// Data is spliced through a pipe without passing through userspace,
+/// falling back to `copy_file_range(2)` on the blocking pool
+/// if the filesystems do not support splice.
+pub async fn copy<H: Handle>(handle: H, from: &Path, to: &Path) -> io::Result<u64> {
+    let stat = actions::statx(&handle, from).await?;
+    let perm = std::fs::Permissions::from_mode((stat.stx_mode & 0o7777) as _);
+
+    let reader = actions::open(&handle, from).await?;
+    let writer = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(perm.mode())
+        .open(&handle, to)
+        .await?;
+
+    // The mode given to `open` is masked by umask. `fchmod` can block,
+    // so it runs on the blocking pool with its own fd while the data is copied.
+    let dup = writer.try_clone()?;
+    let set_perm = blocking::spawn(move || dup.set_permissions(perm));
+
+    let mut reader = Some(reader);
+    let mut writer = Some(writer);
+
+    let len = match splice_all(&handle, &mut reader, 0, &mut writer, Some(0), None).await {
+        Ok((reader, writer, len)) => {
+            actions::close(&handle, reader).await?;
+            actions::close(&handle, writer).await?;
+            len
+        },
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            let (reader, writer) = match (reader, writer) {
+                (Some(reader), Some(writer)) => (reader, writer),
+                _ => return Err(io::Error::from_raw_os_error(libc::EINVAL))
+            };
+
+            // Start over, a partial splice may have written some data already.
+            let (reader, writer, len) = blocking::spawn(move || {
+                let mut len = 0;
+                let mut off_in = 0;
+                let mut off_out = 0;
+
+                loop {
+                    let ret = unsafe {
+                        libc::copy_file_range(
+                            reader.as_raw_fd(), &mut off_in,
+                            writer.as_raw_fd(), &mut off_out,
+                            1 << 30, 0
+                        )
+                    };
+
+                    match ret {
+                        0 => break,
+                        n if n > 0 => len += n as u64,
+                        _ => return Err(io::Error::last_os_error())
+                    }
+                }
+
+                Ok((reader, writer, len))
+            }).await?;
+
+            actions::close(&handle, reader).await?;
+            actions::close(&handle, writer).await?;
+            len
+        },
+        Err(err) => return Err(err)
+    };
+
+    set_perm.await?;
+
+    Ok(len)
+}
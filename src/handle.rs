@@ -1,6 +1,7 @@
-use std::io;
-use io_uring::squeue;
-use crate::{ LocalHandle, sq_submit };
+use std::{ io, slice };
+use std::cell::{ RefCell, RefMut };
+use io_uring::{ squeue, IoUring, SubmissionQueue };
+use crate::{ LocalHandle, Metrics, sq_submit };
 
 
 pub trait Handle {
@@ -10,23 +11,254 @@ pub trait Handle {
     ///
     /// See io_uring submission queue.
     unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<()>;
+
+    /// Push several entries into the io_uring submission queue,
+    /// all of them are guaranteed to be submitted together.
+    ///
+    /// This is required for linked entries, a link is broken
+    /// if the chain is split across submissions.
+    ///
+    /// The default only handles a single entry, anything more fails
+    /// with `ErrorKind::Unsupported`, since `push` alone cannot keep
+    /// entries together. Handles that own a submission queue should override it.
+    ///
+    /// # Safety
+    ///
+    /// See io_uring submission queue.
+    unsafe fn push_multiple(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        match entries {
+            [] => Ok(()),
+            [entry] => self.push(entry),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "This handle cannot push several entries together"
+            ))
+        }
+    }
+
+    /// Push several unrelated entries into the io_uring submission queue.
+    ///
+    /// Unlike [`push_multiple`](Handle::push_multiple), they may be split
+    /// across submissions if the submission queue fills up.
+    ///
+    /// # Safety
+    ///
+    /// See io_uring submission queue.
+    unsafe fn push_batch(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        for entry in entries {
+            self.push(entry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Submit the pushed entries now, without waiting for completions.
+    ///
+    /// Otherwise they are submitted when the proactor next parks or polls.
+    fn submit(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The counters that actions should record into, if any.
+    fn metrics(&self) -> Option<&Metrics> {
+        None
+    }
 }
 
 impl Handle for LocalHandle {
     unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        if self.shared.push_timer(entry) {
+            return Ok(());
+        }
+
         let mut ring = self.ring.borrow_mut();
         let (mut submitter, mut sq, mut cq) = ring.split();
 
         while sq.push(entry).is_err() {
-            sq_submit(&mut submitter, &mut sq, &mut cq, &self.eventfd)?;
+            sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared)?;
+        }
+
+        self.shared.on_push(slice::from_ref(entry));
+
+        Ok(())
+    }
+
+    unsafe fn push_multiple(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        let (mut submitter, mut sq, mut cq) = ring.split();
+
+        if entries.len() > sq.capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many entries for the submission queue"
+            ));
+        }
+
+        while sq.push_multiple(entries).is_err() {
+            sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared)?;
+        }
+
+        self.shared.on_push(entries);
+
+        Ok(())
+    }
+
+    unsafe fn push_batch(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        let (mut submitter, mut sq, mut cq) = ring.split();
+
+        for entry in entries {
+            if self.shared.push_timer(entry) {
+                continue
+            }
+
+            while sq.push(entry).is_err() {
+                sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared)?;
+            }
+
+            self.shared.on_push(slice::from_ref(entry));
         }
 
         Ok(())
     }
+
+    fn submit(&self) -> io::Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        let (mut submitter, mut sq, mut cq) = ring.split();
+
+        sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared)
+    }
+
+    fn metrics(&self) -> Option<&Metrics> {
+        Some(&self.shared.metrics)
+    }
 }
 
 impl<T: Handle> Handle for &'_ T {
     unsafe fn push(&self, entry: &squeue::Entry)  -> io::Result<()>{
         (**self).push(entry)
     }
+
+    unsafe fn push_multiple(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        (**self).push_multiple(entries)
+    }
+
+    unsafe fn push_batch(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        (**self).push_batch(entries)
+    }
+
+    fn submit(&self) -> io::Result<()> {
+        (**self).submit()
+    }
+
+    fn metrics(&self) -> Option<&Metrics> {
+        (**self).metrics()
+    }
+}
+
+/// Scoped guard that holds the submission queue across many pushes,
+/// see [`LocalHandle::batch`].
+pub struct SubmissionBatch<'a> {
+    // Dropped first, this publishes the pushed entries.
+    sq: RefCell<SubmissionQueue<'a>>,
+    ring: RefMut<'a, IoUring>,
+    handle: &'a LocalHandle
+}
+
+impl LocalHandle {
+    /// Borrow the submission queue once for a batch of pushes.
+    ///
+    /// Pushed entries are published to the kernel when the guard is dropped,
+    /// or earlier if the submission queue fills up.
+    ///
+    /// The ring stays borrowed while the guard is alive, so pushing through
+    /// any other handle of the same proactor panics. Do not hold it across
+    /// an await point.
+    pub fn batch(&self) -> SubmissionBatch<'_> {
+        let ring = self.ring.borrow_mut();
+
+        // The ring lives behind the `Rc` for `'a`, and `ring` keeps it exclusively borrowed.
+        let sq = unsafe {
+            let ptr: *const IoUring = &*ring;
+            (*ptr).submission_shared()
+        };
+
+        SubmissionBatch {
+            sq: RefCell::new(sq),
+            ring,
+            handle: self
+        }
+    }
+}
+
+impl SubmissionBatch<'_> {
+    fn submit(&self, sq: &mut SubmissionQueue<'_>) -> io::Result<()> {
+        let mut submitter = self.ring.submitter();
+        let mut cq = unsafe { self.ring.completion_shared() };
+
+        sq_submit(&mut submitter, sq, &mut cq, &self.handle.shared)
+    }
+}
+
+impl Handle for SubmissionBatch<'_> {
+    unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        if self.handle.shared.push_timer(entry) {
+            return Ok(());
+        }
+
+        let mut sq = self.sq.borrow_mut();
+
+        while sq.push(entry).is_err() {
+            self.submit(&mut sq)?;
+        }
+
+        self.handle.shared.on_push(slice::from_ref(entry));
+
+        Ok(())
+    }
+
+    unsafe fn push_multiple(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        let mut sq = self.sq.borrow_mut();
+
+        if entries.len() > sq.capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many entries for the submission queue"
+            ));
+        }
+
+        while sq.push_multiple(entries).is_err() {
+            self.submit(&mut sq)?;
+        }
+
+        self.handle.shared.on_push(entries);
+
+        Ok(())
+    }
+
+    unsafe fn push_batch(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        let mut sq = self.sq.borrow_mut();
+
+        for entry in entries {
+            if self.handle.shared.push_timer(entry) {
+                continue
+            }
+
+            while sq.push(entry).is_err() {
+                self.submit(&mut sq)?;
+            }
+
+            self.handle.shared.on_push(slice::from_ref(entry));
+        }
+
+        Ok(())
+    }
+
+    fn submit(&self) -> io::Result<()> {
+        SubmissionBatch::submit(self, &mut self.sq.borrow_mut())
+    }
+
+    fn metrics(&self) -> Option<&Metrics> {
+        self.handle.metrics()
+    }
 }
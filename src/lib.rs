@@ -1,48 +1,86 @@
 mod loom;
+mod sqe;
 mod ticket;
+mod blocking;
 mod waker;
 mod handle;
+mod timer;
+mod readiness;
+mod pipe;
+mod metrics;
+mod observer;
 pub mod actions;
+pub mod fs;
+pub mod time;
+pub mod buf;
+pub mod signal;
+pub mod process;
 
 use std::io;
 use std::rc::Rc;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::ptr::NonNull;
-use std::cell::RefCell;
-use std::time::Duration;
+use std::cell::{ Cell, RefCell };
+use std::time::{ Duration, Instant };
 use std::future::Future;
 use std::task::{ Context, Poll };
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{ AsRawFd, FromRawFd, RawFd, OwnedFd };
 use futures_task as task;
 use io_uring::{
-    types, opcode,
+    types, opcode, squeue, cqueue,
     IoUring, Submitter,
     SubmissionQueue, CompletionQueue
 };
+use sqe::{ RawEntry, RawCqe, IORING_CQE_F_MORE };
 pub use ticket::{ Ticket, TicketFuture };
-pub use handle::Handle;
+use ticket::multishot::MultiTicket;
+use observer::Observers;
+use timer::TimerWheel;
+pub use handle::{ Handle, SubmissionBatch };
 pub use waker::EventFd;
+pub use readiness::Readiness;
+pub use pipe::{ pipe, PipeReader, PipeWriter };
+pub use metrics::Metrics;
+pub use observer::{ OpObserver, OpInfo, OpCompletion };
 
 
 pub struct Proactor {
     ring: Rc<RefCell<IoUring>>,
     eventbuf: Box<[u8; 8]>,
-    eventfd: Arc<EventFd>,
+    shared: Rc<Shared>,
+    wheelbuf: Box<types::Timespec>,
 }
 
 #[derive(Clone)]
 pub struct LocalHandle {
     ring: Rc<RefCell<IoUring>>,
+    shared: Rc<Shared>,
+}
+
+/// State shared by a proactor and its handles.
+struct Shared {
     eventfd: Arc<EventFd>,
+    timers: TimerWheel,
+    metrics: Metrics,
+    observers: Observers,
+
+    // Completions dropped by the kernel and not reported yet.
+    dropped: Cell<u64>
 }
 
 const WAKE_TOKEN: u64 = 0x0;
 const EMPTY_TOKEN: u64 = 0x1;
+const WHEEL_TOKEN: u64 = 0x2;
+
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+/// Set on the user data of multishot requests, ticket pointers are always aligned.
+const MULTISHOT_TAG: u64 = 0x1;
 
 impl Proactor {
     pub fn new() -> io::Result<Proactor> {
-        Self::with_builder(Default::default(), 256)
+        Self::with_builder(IoUring::builder(), 256)
     }
 
     pub fn with_builder(builder: io_uring::Builder, entries: u32) -> io::Result<Proactor> {
@@ -52,43 +90,168 @@ impl Proactor {
         Ok(Proactor {
             ring: Rc::new(RefCell::new(ring)),
             eventbuf: Box::new([0; 8]),
-            eventfd: Arc::new(eventfd)
+            shared: Rc::new(Shared {
+                eventfd: Arc::new(eventfd),
+                timers: TimerWheel::new(),
+                metrics: Metrics::default(),
+                observers: Observers::default(),
+                dropped: Cell::new(0)
+            }),
+            wheelbuf: Box::new(types::Timespec::new())
         })
     }
 
     pub fn handle(&self) -> LocalHandle {
         LocalHandle {
             ring: Rc::clone(&self.ring),
-            eventfd: Arc::clone(&self.eventfd)
+            shared: Rc::clone(&self.shared)
         }
     }
 
     pub fn waker(&self) -> &Arc<EventFd> {
-        &self.eventfd
+        &self.shared.eventfd
+    }
+
+    /// Runtime counters of this proactor and its handles.
+    pub fn metrics(&self) -> &Metrics {
+        &self.shared.metrics
+    }
+
+    /// Observe every request pushed through this proactor's handles and its completions,
+    /// for example to record per-opcode latency. `None` removes the observer.
+    ///
+    /// Requests are only tracked while an observer is set or, with the `tracing` feature,
+    /// while `DEBUG` events of the `ritsu::op` target are enabled.
+    pub fn set_observer(&self, observer: Option<Rc<dyn OpObserver>>) {
+        self.shared.observers.set(observer);
+    }
+
+    /// Enable the timer wheel.
+    ///
+    /// Timeouts pushed afterwards through this proactor's handles, like those of
+    /// [`time::sleep`] and [`time::interval`], are kept in a hierarchical wheel
+    /// with millisecond resolution instead of each owning a kernel timeout.
+    /// Only a single timeout, armed for the nearest deadline, is kept in flight.
+    ///
+    /// Removes and updates of them are handled by the wheel too. Timeouts that are
+    /// linked, carry other flags, or count completions still go to the kernel.
+    pub fn enable_timer_wheel(&self) {
+        self.shared.timers.enable();
+    }
+
+    /// Allocate `count` buffers of `size` bytes and register them with the ring,
+    /// for use with [`read_fixed`](actions::io::read_fixed) and [`write_fixed`](actions::io::write_fixed).
+    ///
+    /// Only one set of buffers can be registered per ring.
+    pub fn register_buffers(&self, count: u16, size: usize) -> io::Result<Vec<buf::FixedBuf>> {
+        let mut bufs = (0..count)
+            .map(|index| buf::FixedBuf::new(vec![0; size].into_boxed_slice(), index))
+            .collect::<Vec<_>>();
+
+        let iovecs = bufs.iter_mut()
+            .map(|buf| libc::iovec {
+                iov_base: buf::IoBufMut::stable_mut_ptr(buf).cast(),
+                iov_len: size
+            })
+            .collect::<Vec<_>>();
+
+        self.ring.borrow().submitter().register_buffers(&iovecs)?;
+
+        Ok(bufs)
+    }
+
+    /// Create an eventfd that the kernel signals whenever a completion is posted,
+    /// with `IORING_REGISTER_EVENTFD`.
+    ///
+    /// Only one eventfd can be registered per ring.
+    /// See [`poll_completions`](Proactor::poll_completions).
+    pub fn register_eventfd(&self) -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        self.ring.borrow().submitter().register_eventfd(fd.as_raw_fd())?;
+
+        Ok(fd)
+    }
+
+    /// Submit pushed entries and dispatch the pending completions without parking,
+    /// returns the number of completions.
+    ///
+    /// This allows to drive the proactor from another event loop instead of [`block_on`].
+    /// Wait for the ring fd (see [`AsRawFd`]) or a registered eventfd to become readable,
+    /// then call this. It must also be called after polling tasks,
+    /// so that the entries they pushed get submitted.
+    ///
+    /// Like [`park`](Proactor::park), it fails if the kernel dropped completions.
+    pub fn poll_completions(&mut self) -> io::Result<usize> {
+        let mut ring = self.ring.borrow_mut();
+        let (mut submitter, mut sq, mut cq) = ring.split();
+
+        cq.sync();
+        let mut count = cq_consume(&mut cq, &self.shared);
+
+        if self.shared.timers.is_enabled() {
+            self.shared.fire_timers();
+
+            let armed = self.shared.timers.armed();
+            if let Some(entry) = wheel_entry(&self.shared.timers, &mut self.wheelbuf) {
+                if unsafe { sq.push(&entry).is_err() } {
+                    self.shared.timers.set_armed(armed);
+                } else {
+                    self.shared.metrics.on_push(1);
+                }
+            }
+        }
+
+        match sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared) {
+            Ok(()) => (),
+
+            // Completions are pending, submit again on the next call.
+            Err(ref err) if err.raw_os_error() == Some(libc::EBUSY) => (),
+            Err(err) => return Err(err)
+        }
+
+        count += cq_flush(&submitter, &sq, &mut cq, &self.shared)?;
+        self.shared.take_dropped()?;
+
+        Ok(count)
     }
 
+    /// Submit pushed entries, wait for completions up to `dur` and dispatch them.
+    ///
+    /// Completions that the kernel kept back while the completion queue was full
+    /// are flushed. If the kernel had to drop completions, the requests they belong to
+    /// never complete, and this returns an error once for them.
     pub fn park(&mut self, dur: Option<Duration>) -> io::Result<()> {
         let mut ring = self.ring.borrow_mut();
         let (mut submitter, mut sq, mut cq) = ring.split();
 
         // clean cq
-        cq_consume(&mut cq, &self.eventfd);
+        cq_consume(&mut cq, &self.shared);
 
-        let state = self.eventfd.park();
+        if self.shared.timers.is_enabled() {
+            self.shared.fire_timers();
+        }
+
+        let state = self.shared.eventfd.park();
 
         // we has events, so we don't need to wait for timeout
         let nowait = state.is_ready()
             || dur == Some(Duration::from_secs(0));
 
         if !state.is_parking() {
-            let op = types::Fd(self.eventfd.as_raw_fd());
+            let op = types::Fd(self.shared.eventfd.as_raw_fd());
             let bufptr = self.eventbuf.as_mut_ptr();
             let entry = opcode::Read::new(op, bufptr, 8)
                 .build()
                 .user_data(WAKE_TOKEN);
 
             if sq.is_full() {
-                match sq_submit(&mut submitter, &mut sq, &mut cq, &self.eventfd) {
+                match sq_submit(&mut submitter, &mut sq, &mut cq, &self.shared) {
                     Ok(()) => (),
                     Err(ref err) if err.raw_os_error() == Some(libc::EBUSY) => (),
                     Err(err) => return Err(err)
@@ -98,11 +261,26 @@ impl Proactor {
             if unsafe { sq.push(&entry).is_err() } {
                 // If the new park entry cannot be pushed in,
                 // it will be postponed until the next time.
-                self.eventfd.unpark();
+                self.shared.eventfd.unpark();
+            } else {
+                self.shared.metrics.on_push(1);
             }
         };
 
-        drop(sq);
+        let armed = self.shared.timers.armed();
+        if let Some(entry) = wheel_entry(&self.shared.timers, &mut self.wheelbuf) {
+            // If it cannot be pushed in, it will be armed next time.
+            if unsafe { sq.push(&entry).is_err() } {
+                self.shared.timers.set_armed(armed);
+            } else {
+                self.shared.metrics.on_push(1);
+            }
+        }
+
+        sq.sync();
+        self.shared.observers.on_submit();
+
+        let now = Instant::now();
 
         while let Err(err) =
             if nowait {
@@ -119,20 +297,38 @@ impl Proactor {
             }
         {
             if err.raw_os_error() == Some(libc::EBUSY) {
+                self.shared.metrics.on_ebusy();
                 cq.sync();
-                cq_consume(&mut cq, &self.eventfd);
+                cq_consume(&mut cq, &self.shared);
             } else {
                 return Err(err);
             }
         }
 
+        self.shared.metrics.on_submit();
+        self.shared.metrics.on_park(now.elapsed());
+
         cq.sync();
-        cq_consume(&mut cq, &self.eventfd);
+        cq_consume(&mut cq, &self.shared);
+        cq_flush(&submitter, &sq, &mut cq, &self.shared)?;
+
+        if self.shared.timers.is_enabled() {
+            self.shared.fire_timers();
+        }
 
         // reset eventfd
-        self.eventfd.reset();
+        self.shared.eventfd.reset();
 
-        Ok(())
+        self.shared.take_dropped()
+    }
+}
+
+
+impl AsRawFd for Proactor {
+    /// The ring fd, it is readable while completions are pending.
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.ring.borrow().as_raw_fd()
     }
 }
 
@@ -141,11 +337,11 @@ pub fn block_on<F: Future>(proactor: &mut Proactor, mut f: F) -> io::Result<F::O
     {
         let mut ring = proactor.ring.borrow_mut();
         let (mut submitter, mut sq, mut cq) = ring.split();
-        sq_submit(&mut submitter, &mut sq, &mut cq, &proactor.eventfd)?;
+        sq_submit(&mut submitter, &mut sq, &mut cq, &proactor.shared)?;
     }
 
     loop {
-        let waker = task::waker_ref(&proactor.eventfd);
+        let waker = task::waker_ref(&proactor.shared.eventfd);
         let mut cx = Context::from_waker(&waker);
 
         let f = unsafe {
@@ -161,24 +357,190 @@ pub fn block_on<F: Future>(proactor: &mut Proactor, mut f: F) -> io::Result<F::O
 }
 
 
-fn cq_consume(cq: &mut CompletionQueue<'_>, eventfd: &EventFd) {
+impl Shared {
+    #[inline]
+    fn on_push(&self, entries: &[squeue::Entry]) {
+        self.metrics.on_push(entries.len());
+        self.observers.on_push(entries);
+
+        // A drained entry waits for the eventfd read of a parked proactor,
+        // complete the read like a wake up does.
+        if entries.iter().any(is_drain) {
+            task::ArcWake::wake_by_ref(&self.eventfd);
+        }
+    }
+
+    /// Keep a timeout pushed through a handle in the timer wheel,
+    /// returns false if it has to go to the kernel.
+    ///
+    /// # Safety
+    ///
+    /// The entry must be valid, as for pushing it.
+    unsafe fn push_timer(&self, entry: &squeue::Entry) -> bool {
+        match self.timers.take(entry) {
+            Some(completions) => {
+                for (user_data, res) in completions {
+                    dispatch(RawCqe { user_data, res, flags: 0 }.build(), self);
+                }
+
+                true
+            },
+            None => false
+        }
+    }
+
+    fn fire_timers(&self) {
+        for (user_data, more) in self.timers.fire(Instant::now()) {
+            let flags = if more { IORING_CQE_F_MORE } else { 0 };
+            dispatch(RawCqe { user_data, res: -libc::ETIME, flags }.build(), self);
+        }
+    }
+
+    fn take_dropped(&self) -> io::Result<()> {
+        match self.dropped.replace(0) {
+            0 => Ok(()),
+            n => Err(io::Error::other(
+                format!("Completion queue overflowed, {} completions were dropped", n)
+            ))
+        }
+    }
+}
+
+fn is_drain(entry: &squeue::Entry) -> bool {
+    let flags = RawEntry::from_entry(entry.clone()).flags;
+    flags & squeue::Flags::IO_DRAIN.bits() != 0
+}
+
+/// Build the entry that keeps the kernel timeout armed for the nearest deadline.
+///
+/// An armed timeout is only moved earlier, a later one fires spuriously
+/// and is armed again. The timespec is copied by the kernel on submission.
+fn wheel_entry(timers: &TimerWheel, wheelbuf: &mut types::Timespec) -> Option<squeue::Entry> {
+    if !timers.is_enabled() {
+        return None;
+    }
+
+    let deadline = timers.next_deadline()?;
+
+    let entry = match timers.armed() {
+        None => {
+            *wheelbuf = time::abs_timespec(deadline);
+            opcode::Timeout::new(&*wheelbuf)
+                .flags(types::TimeoutFlags::ABS)
+                .build()
+                .user_data(WHEEL_TOKEN)
+        },
+        Some(armed) if deadline < armed => {
+            *wheelbuf = time::abs_timespec(deadline);
+            RawEntry {
+                opcode: opcode::TimeoutRemove::CODE,
+                fd: -1,
+                addr: WHEEL_TOKEN,
+                off: &*wheelbuf as *const types::Timespec as _,
+                op_flags: (types::TimeoutFlags::UPDATE | types::TimeoutFlags::ABS).bits(),
+                user_data: EMPTY_TOKEN,
+                ..Default::default()
+            }
+                .build()
+        },
+        Some(_) => return None
+    };
+
+    timers.set_armed(Some(deadline));
+
+    Some(entry)
+}
+
+/// Dispatch the completions in the queue, returns their number.
+fn cq_consume(cq: &mut CompletionQueue<'_>, shared: &Shared) -> usize {
+    let dropped = shared.metrics.update_cq_overflow(cq.overflow());
+    if dropped != 0 {
+        shared.dropped.set(shared.dropped.get() + dropped as u64);
+    }
+
+    let count = cq.len();
+
     for entry in cq {
-        match entry.user_data() {
-            WAKE_TOKEN => eventfd.unpark(),
-            EMPTY_TOKEN => (),
-            ptr => unsafe {
-                Ticket::from_raw(NonNull::new_unchecked(ptr as _))
-                    .send(entry);
+        let last = dispatch(entry, shared);
+        shared.metrics.on_cqe(last);
+    }
+
+    count
+}
+
+/// Hand a completion to the request it belongs to, returns false if more follow.
+fn dispatch(entry: cqueue::Entry, shared: &Shared) -> bool {
+    match entry.user_data() {
+        WAKE_TOKEN => {
+            shared.metrics.on_wake();
+            shared.eventfd.unpark();
+            true
+        },
+        EMPTY_TOKEN => true,
+        WHEEL_TOKEN => {
+            shared.timers.set_armed(None);
+            true
+        },
+        ptr if ptr & MULTISHOT_TAG == MULTISHOT_TAG => unsafe {
+            let ptr = NonNull::new_unchecked((ptr & !MULTISHOT_TAG) as _);
+            let ticket = MultiTicket::from_raw(ptr);
+            let more = io_uring::cqueue::more(entry.flags());
+
+            shared.observers.on_complete(&entry, more);
+            ticket.send(entry, more);
+
+            // The kernel, or the timer wheel, still owns the ticket.
+            if more {
+                ticket.into_raw();
             }
+
+            !more
+        },
+        ptr => unsafe {
+            shared.observers.on_complete(&entry, false);
+            Ticket::from_raw(NonNull::new_unchecked(ptr as _))
+                .send(entry);
+            true
         }
     }
 }
 
+/// Flush the completions that the kernel kept back while the completion queue was full,
+/// and dispatch them. Returns their number.
+///
+/// The kernel only moves them into the queue when entered with `GETEVENTS`,
+/// which does not happen if nothing is submitted or waited for.
+fn cq_flush(
+    submitter: &Submitter,
+    sq: &SubmissionQueue<'_>,
+    cq: &mut CompletionQueue<'_>,
+    shared: &Shared
+) -> io::Result<usize> {
+    let mut count = 0;
+
+    while sq.cq_overflow() {
+        shared.metrics.on_overflow_flush();
+
+        unsafe {
+            submitter.enter::<libc::sigset_t>(0, 0, IORING_ENTER_GETEVENTS, None)?;
+        }
+
+        cq.sync();
+
+        match cq_consume(cq, shared) {
+            0 => break,
+            n => count += n
+        }
+    }
+
+    Ok(count)
+}
+
 fn sq_submit(
     submitter: &mut Submitter,
     sq: &mut SubmissionQueue<'_>,
     cq: &mut CompletionQueue<'_>,
-    eventfd: &EventFd
+    shared: &Shared
 ) -> io::Result<()> {
     sq.sync();
 
@@ -186,33 +548,42 @@ fn sq_submit(
         return Ok(())
     }
 
+    shared.observers.on_submit();
+
+    // Retries that made no room in the completion queue.
     let mut count = 0;
 
     while let Err(err) = submitter.submit() {
         if err.raw_os_error() == Some(libc::EBUSY) && count < 3 {
-            count += 1;
+            shared.metrics.on_ebusy();
             cq.sync();
-            cq_consume(cq, eventfd);
+
+            match cq_consume(cq, shared) {
+                0 => count += 1,
+                _ => count = 0
+            }
         } else {
             return Err(err);
         }
     }
 
+    shared.metrics.on_submit();
+
     Ok(())
 }
 
 impl Drop for Proactor {
     fn drop(&mut self) {
-        if self.eventfd.load().is_parking() {
+        if self.shared.eventfd.load().is_parking() {
             let mut ring = self.ring.borrow_mut();
-            proactor_drop(&mut ring, &self.eventfd).unwrap();
+            proactor_drop(&mut ring, &self.shared).unwrap();
         }
     }
 }
 
 #[cold]
-fn proactor_drop(ring: &mut IoUring, eventfd: &EventFd) -> io::Result<()> {
-    let (mut submitter, mut sq, mut cq) = ring.split();
+fn proactor_drop(ring: &mut IoUring, shared: &Shared) -> io::Result<()> {
+    let (submitter, mut sq, mut cq) = ring.split();
 
     for entry in &mut cq {
         if entry.user_data() == WAKE_TOKEN {
@@ -220,15 +591,9 @@ fn proactor_drop(ring: &mut IoUring, eventfd: &EventFd) -> io::Result<()> {
         }
     }
 
-    let cancel_e = opcode::AsyncCancel::new(WAKE_TOKEN)
-        .build()
-        .user_data(EMPTY_TOKEN);
-
-    unsafe {
-        while sq.push(&cancel_e).is_err() {
-            sq_submit(&mut submitter, &mut sq, &mut cq, eventfd)?;
-        }
-    }
+    // A cancel misses the read if it was queued behind a drained entry,
+    // complete it like a wake up does.
+    task::ArcWake::wake_by_ref(&shared.eventfd);
 
     sq.sync();
 
@@ -248,3 +613,33 @@ fn proactor_drop(ring: &mut IoUring, eventfd: &EventFd) -> io::Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drop_two(proactor: &Proactor) {
+        // The kernel counter is 0, so the next consume sees it wrap by 2.
+        proactor.shared.metrics.update_cq_overflow(u32::MAX - 1);
+    }
+
+    fn assert_dropped(ret: io::Result<()>) {
+        let err = ret.unwrap_err();
+        assert_eq!(err.to_string(), "Completion queue overflowed, 2 completions were dropped");
+    }
+
+    #[test]
+    fn dropped_completions_fail_once() {
+        let mut proactor = Proactor::new().unwrap();
+        let total = proactor.metrics().cq_overflow();
+
+        drop_two(&proactor);
+        assert_dropped(proactor.poll_completions().map(drop));
+        assert_eq!(proactor.poll_completions().unwrap(), 0);
+        assert_eq!(proactor.metrics().cq_overflow() - total, u32::MAX as u64 + 1);
+
+        drop_two(&proactor);
+        assert_dropped(proactor.park(Some(Duration::from_secs(0))));
+        proactor.park(Some(Duration::from_secs(0))).unwrap();
+    }
+}
@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 
+#[cfg(feature = "loom")]
+pub use ::loom::{ sync, cell };
+
+#[cfg(not(feature = "loom"))]
 pub use std::sync;
 
+#[cfg(not(feature = "loom"))]
 pub mod cell {
     pub struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
 
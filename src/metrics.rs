@@ -0,0 +1,190 @@
+use std::fmt;
+use std::cell::Cell;
+use std::time::Duration;
+
+
+/// Runtime counters of a [`Proactor`](crate::Proactor), see [`Proactor::metrics`](crate::Proactor::metrics).
+///
+/// They are plain cells updated on the proactor thread, cheap enough to always keep on.
+/// Apart from `in_flight`, they are totals since the proactor was created.
+#[derive(Default)]
+pub struct Metrics {
+    sqes_pushed: Cell<u64>,
+    submits: Cell<u64>,
+    ebusy_retries: Cell<u64>,
+    cqes: Cell<u64>,
+    wakes: Cell<u64>,
+    parks: Cell<u64>,
+    park_time: Cell<Duration>,
+    in_flight: Cell<u64>,
+    cq_overflow: Cell<u64>,
+    cq_overflow_raw: Cell<u32>,
+    overflow_flushes: Cell<u64>,
+    bytes_read: Cell<u64>,
+    bytes_written: Cell<u64>
+}
+
+#[inline]
+fn incr(cell: &Cell<u64>, n: u64) {
+    cell.set(cell.get().wrapping_add(n));
+}
+
+impl Metrics {
+    /// Entries pushed into the submission queue, including the proactor's own.
+    #[inline]
+    pub fn sqes_pushed(&self) -> u64 {
+        self.sqes_pushed.get()
+    }
+
+    /// Successful submit calls.
+    #[inline]
+    pub fn submits(&self) -> u64 {
+        self.submits.get()
+    }
+
+    /// Submits retried because the kernel returned `EBUSY`.
+    #[inline]
+    pub fn ebusy_retries(&self) -> u64 {
+        self.ebusy_retries.get()
+    }
+
+    /// Completions consumed.
+    #[inline]
+    pub fn cqes(&self) -> u64 {
+        self.cqes.get()
+    }
+
+    /// Completions of the eventfd read that wakes a parked proactor.
+    #[inline]
+    pub fn wakes(&self) -> u64 {
+        self.wakes.get()
+    }
+
+    /// Calls to [`Proactor::park`](crate::Proactor::park).
+    #[inline]
+    pub fn parks(&self) -> u64 {
+        self.parks.get()
+    }
+
+    /// Time spent in the kernel while parked.
+    #[inline]
+    pub fn park_time(&self) -> Duration {
+        self.park_time.get()
+    }
+
+    /// Pushed entries whose final completion has not been consumed yet.
+    #[inline]
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.get()
+    }
+
+    /// Completions the kernel dropped because the completion queue was full.
+    #[inline]
+    pub fn cq_overflow(&self) -> u64 {
+        self.cq_overflow.get()
+    }
+
+    /// Times completions that the kernel kept back while the completion queue
+    /// was full had to be flushed into it.
+    #[inline]
+    pub fn overflow_flushes(&self) -> u64 {
+        self.overflow_flushes.get()
+    }
+
+    /// Bytes read by the read actions of [`actions::io`](crate::actions::io).
+    #[inline]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.get()
+    }
+
+    /// Bytes written by the write actions of [`actions::io`](crate::actions::io).
+    #[inline]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.get()
+    }
+
+    #[inline]
+    pub(crate) fn on_push(&self, n: usize) {
+        incr(&self.sqes_pushed, n as u64);
+        incr(&self.in_flight, n as u64);
+    }
+
+    #[inline]
+    pub(crate) fn on_submit(&self) {
+        incr(&self.submits, 1);
+    }
+
+    #[inline]
+    pub(crate) fn on_ebusy(&self) {
+        incr(&self.ebusy_retries, 1);
+    }
+
+    /// `last` is false for a multishot completion that is followed by more.
+    #[inline]
+    pub(crate) fn on_cqe(&self, last: bool) {
+        incr(&self.cqes, 1);
+
+        if last {
+            self.in_flight.set(self.in_flight.get().saturating_sub(1));
+        }
+    }
+
+    #[inline]
+    pub(crate) fn on_wake(&self) {
+        incr(&self.wakes, 1);
+    }
+
+    #[inline]
+    pub(crate) fn on_park(&self, dur: Duration) {
+        incr(&self.parks, 1);
+        self.park_time.set(self.park_time.get() + dur);
+    }
+
+    /// Takes the overflow counter of the kernel, which wraps at `u32`,
+    /// returns the number of completions dropped since the last call.
+    #[inline]
+    pub(crate) fn update_cq_overflow(&self, raw: u32) -> u32 {
+        let dropped = raw.wrapping_sub(self.cq_overflow_raw.get());
+
+        if dropped != 0 {
+            self.cq_overflow_raw.set(raw);
+            incr(&self.cq_overflow, dropped as u64);
+        }
+
+        dropped
+    }
+
+    #[inline]
+    pub(crate) fn on_overflow_flush(&self) {
+        incr(&self.overflow_flushes, 1);
+    }
+
+    #[inline]
+    pub(crate) fn on_read(&self, n: usize) {
+        incr(&self.bytes_read, n as u64);
+    }
+
+    #[inline]
+    pub(crate) fn on_write(&self, n: usize) {
+        incr(&self.bytes_written, n as u64);
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("sqes_pushed", &self.sqes_pushed())
+            .field("submits", &self.submits())
+            .field("ebusy_retries", &self.ebusy_retries())
+            .field("cqes", &self.cqes())
+            .field("wakes", &self.wakes())
+            .field("parks", &self.parks())
+            .field("park_time", &self.park_time())
+            .field("in_flight", &self.in_flight())
+            .field("cq_overflow", &self.cq_overflow())
+            .field("overflow_flushes", &self.overflow_flushes())
+            .field("bytes_read", &self.bytes_read())
+            .field("bytes_written", &self.bytes_written())
+            .finish()
+    }
+}
@@ -0,0 +1,265 @@
+//! Per-request hooks, see [`Proactor::set_observer`](crate::Proactor::set_observer).
+//!
+//! With the `tracing` feature, every request also emits a `tracing` event
+//! on push and on completion, under the `ritsu::op` target, while
+//! its `DEBUG` level is enabled.
+
+use std::io;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{ Duration, Instant };
+use std::os::unix::io::RawFd;
+use io_uring::{ opcode, squeue, cqueue };
+use crate::sqe::{ RawEntry, IORING_OP_WAITID, IORING_OP_FTRUNCATE };
+use crate::WHEEL_TOKEN;
+
+
+/// Receives every request pushed through a proactor's handles and its completions.
+///
+/// It is called while the ring is borrowed, so it must not push
+/// or otherwise use a handle of the same proactor.
+pub trait OpObserver {
+    /// A request was pushed into the submission queue.
+    fn on_submit(&self, _op: &OpInfo) {}
+
+    /// A completion of the request arrived.
+    ///
+    /// A multishot request completes several times with the same `op`.
+    fn on_complete(&self, op: &OpInfo, completion: &OpCompletion);
+}
+
+/// A pushed request.
+#[derive(Debug, Clone, Copy)]
+pub struct OpInfo {
+    pub user_data: u64,
+    pub opcode: u8,
+    pub fd: RawFd
+}
+
+/// A completion of a request.
+#[derive(Debug, Clone, Copy)]
+pub struct OpCompletion {
+    pub result: i32,
+
+    /// Time from the submit call that sent the request to the kernel to this completion.
+    pub latency: Duration,
+
+    /// More completions follow, for multishot requests.
+    pub more: bool
+}
+
+impl OpInfo {
+    /// The name of the opcode, like `"read"`.
+    pub fn name(&self) -> &'static str {
+        opcode_name(self.opcode)
+    }
+
+    /// Whether the result of this opcode is a byte count.
+    pub fn transfers_bytes(&self) -> bool {
+        matches!(
+            self.opcode,
+            opcode::Read::CODE | opcode::Write::CODE
+                | opcode::Readv::CODE | opcode::Writev::CODE
+                | opcode::ReadFixed::CODE | opcode::WriteFixed::CODE
+                | opcode::Recv::CODE | opcode::Send::CODE
+                | opcode::RecvMsg::CODE | opcode::SendMsg::CODE
+                | opcode::Splice::CODE | opcode::Tee::CODE
+        )
+    }
+}
+
+impl OpCompletion {
+    pub fn error(&self) -> Option<io::Error> {
+        if self.result < 0 {
+            Some(io::Error::from_raw_os_error(-self.result))
+        } else {
+            None
+        }
+    }
+
+    /// The byte count, for requests that transfer bytes and succeeded.
+    pub fn bytes(&self, op: &OpInfo) -> Option<usize> {
+        if op.transfers_bytes() && self.result >= 0 {
+            Some(self.result as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Pending requests by user data, only tracked while something observes them.
+///
+/// A request is stamped when it is submitted, not when it is pushed,
+/// so that its latency does not include the time spent in the queue.
+#[derive(Default)]
+pub(crate) struct Observers {
+    observer: RefCell<Option<Rc<dyn OpObserver>>>,
+    pending: RefCell<HashMap<u64, (OpInfo, Option<Instant>)>>,
+    unsubmitted: RefCell<Vec<u64>>
+}
+
+impl Observers {
+    pub(crate) fn set(&self, observer: Option<Rc<dyn OpObserver>>) {
+        *self.observer.borrow_mut() = observer;
+    }
+
+    #[inline]
+    fn is_active(&self) -> bool {
+        #[cfg(feature = "tracing")]
+        if tracing::enabled!(target: "ritsu::op", tracing::Level::DEBUG) {
+            return true
+        }
+
+        self.observer.borrow().is_some()
+    }
+
+    pub(crate) fn on_push(&self, entries: &[squeue::Entry]) {
+        if !self.is_active() {
+            return
+        }
+
+        let observer = self.observer.borrow().clone();
+
+        for entry in entries {
+            let raw = RawEntry::from_entry(entry.clone());
+
+            // Tokens are shared by many requests.
+            if raw.user_data <= WHEEL_TOKEN {
+                continue
+            }
+
+            let op = OpInfo {
+                user_data: raw.user_data,
+                opcode: raw.opcode,
+                fd: raw.fd
+            };
+
+            self.pending.borrow_mut().insert(op.user_data, (op, None));
+            self.unsubmitted.borrow_mut().push(op.user_data);
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                target: "ritsu::op",
+                user_data = op.user_data,
+                opcode = op.name(),
+                fd = op.fd,
+                "submit"
+            );
+
+            if let Some(observer) = observer.as_ref() {
+                observer.on_submit(&op);
+            }
+        }
+    }
+
+    /// Stamp the requests pushed since the last submit, right before submitting them.
+    #[inline]
+    pub(crate) fn on_submit(&self) {
+        let mut unsubmitted = self.unsubmitted.borrow_mut();
+
+        if unsubmitted.is_empty() {
+            return
+        }
+
+        let now = Instant::now();
+        let mut pending = self.pending.borrow_mut();
+
+        for user_data in unsubmitted.drain(..) {
+            if let Some((_, start @ None)) = pending.get_mut(&user_data) {
+                *start = Some(now);
+            }
+        }
+    }
+
+    pub(crate) fn on_complete(&self, entry: &cqueue::Entry, more: bool) {
+        let mut pending = self.pending.borrow_mut();
+
+        let (op, start) = if more {
+            match pending.get(&entry.user_data()) {
+                Some(&pair) => pair,
+                None => return
+            }
+        } else {
+            match pending.remove(&entry.user_data()) {
+                Some(pair) => pair,
+                None => return
+            }
+        };
+
+        drop(pending);
+
+        let completion = OpCompletion {
+            result: entry.result(),
+            // Unstamped if it completed before a submit of the proactor ran.
+            latency: start.map_or(Duration::ZERO, |start| start.elapsed()),
+            more
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "ritsu::op",
+            user_data = op.user_data,
+            opcode = op.name(),
+            fd = op.fd,
+            result = completion.result,
+            bytes = completion.bytes(&op),
+            errno = (completion.result < 0).then(|| -completion.result),
+            latency_us = completion.latency.as_micros() as u64,
+            more,
+            "complete"
+        );
+
+        let observer = self.observer.borrow().clone();
+        if let Some(observer) = observer {
+            observer.on_complete(&op, &completion);
+        }
+    }
+}
+
+fn opcode_name(code: u8) -> &'static str {
+    match code {
+        opcode::Nop::CODE => "nop",
+        opcode::Readv::CODE => "readv",
+        opcode::Writev::CODE => "writev",
+        opcode::Fsync::CODE => "fsync",
+        opcode::ReadFixed::CODE => "read_fixed",
+        opcode::WriteFixed::CODE => "write_fixed",
+        opcode::PollAdd::CODE => "poll_add",
+        opcode::PollRemove::CODE => "poll_remove",
+        opcode::SyncFileRange::CODE => "sync_file_range",
+        opcode::SendMsg::CODE => "sendmsg",
+        opcode::RecvMsg::CODE => "recvmsg",
+        opcode::Timeout::CODE => "timeout",
+        opcode::TimeoutRemove::CODE => "timeout_remove",
+        opcode::Accept::CODE => "accept",
+        opcode::AsyncCancel::CODE => "async_cancel",
+        opcode::LinkTimeout::CODE => "link_timeout",
+        opcode::Connect::CODE => "connect",
+        opcode::Fallocate64::CODE => "fallocate",
+        opcode::OpenAt::CODE => "openat",
+        opcode::Close::CODE => "close",
+        opcode::Statx::CODE => "statx",
+        opcode::Read::CODE => "read",
+        opcode::Write::CODE => "write",
+        opcode::Fadvise::CODE => "fadvise",
+        opcode::Madvise::CODE => "madvise",
+        opcode::Send::CODE => "send",
+        opcode::Recv::CODE => "recv",
+        opcode::OpenAt2::CODE => "openat2",
+        opcode::EpollCtl::CODE => "epoll_ctl",
+        opcode::Splice::CODE => "splice",
+        opcode::ProvideBuffers::CODE => "provide_buffers",
+        opcode::RemoveBuffers::CODE => "remove_buffers",
+        opcode::Tee::CODE => "tee",
+        opcode::Shutdown::CODE => "shutdown",
+        opcode::RenameAt::CODE => "renameat",
+        opcode::UnlinkAt::CODE => "unlinkat",
+        opcode::MkDirAt::CODE => "mkdirat",
+        opcode::SymlinkAt::CODE => "symlinkat",
+        opcode::LinkAt::CODE => "linkat",
+        IORING_OP_WAITID => "waitid",
+        IORING_OP_FTRUNCATE => "ftruncate",
+        _ => "unknown"
+    }
+}
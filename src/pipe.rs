@@ -0,0 +1,78 @@
+use std::io;
+use std::process::Stdio;
+use std::os::unix::io::{ AsRawFd, FromRawFd, IntoRawFd, RawFd, OwnedFd };
+use crate::actions::io::TrustedAsRawFd;
+
+
+/// The read end of a pipe, see [`pipe`].
+#[derive(Debug)]
+pub struct PipeReader(OwnedFd);
+
+/// The write end of a pipe, see [`pipe`].
+#[derive(Debug)]
+pub struct PipeWriter(OwnedFd);
+
+/// Create a pipe with `O_CLOEXEC`.
+///
+/// Both ends can be used with [`read_buf`](crate::actions::io::read_buf)
+/// and [`write_buf`](crate::actions::io::write_buf), or handed to a child process.
+/// Wrap them in [`CloseOnDrop`](crate::actions::fs::CloseOnDrop) to close them through the ring.
+pub fn pipe() -> io::Result<(PipeReader, PipeWriter)> {
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    unsafe {
+        Ok((
+            PipeReader(OwnedFd::from_raw_fd(fds[0])),
+            PipeWriter(OwnedFd::from_raw_fd(fds[1]))
+        ))
+    }
+}
+
+macro_rules! impl_fd {
+    ( $( $name:ident ),* ) => {
+        $(
+            impl AsRawFd for $name {
+                #[inline]
+                fn as_raw_fd(&self) -> RawFd {
+                    self.0.as_raw_fd()
+                }
+            }
+
+            impl IntoRawFd for $name {
+                #[inline]
+                fn into_raw_fd(self) -> RawFd {
+                    self.0.into_raw_fd()
+                }
+            }
+
+            impl FromRawFd for $name {
+                #[inline]
+                unsafe fn from_raw_fd(fd: RawFd) -> $name {
+                    $name(OwnedFd::from_raw_fd(fd))
+                }
+            }
+
+            impl From<$name> for OwnedFd {
+                #[inline]
+                fn from(pipe: $name) -> OwnedFd {
+                    pipe.0
+                }
+            }
+
+            impl From<$name> for Stdio {
+                #[inline]
+                fn from(pipe: $name) -> Stdio {
+                    Stdio::from(pipe.0)
+                }
+            }
+
+            unsafe impl TrustedAsRawFd for $name {}
+        )*
+    }
+}
+
+impl_fd!(PipeReader, PipeWriter);
@@ -0,0 +1,262 @@
+//! Child processes, waited on through the ring.
+
+use std::{ io, mem };
+use std::ffi::OsStr;
+use std::path::Path;
+use std::os::unix::io::{ AsRawFd, FromRawFd, IntoRawFd, OwnedFd };
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ self, Stdio, ExitStatus, ChildStdin, ChildStdout, ChildStderr };
+use io_uring::{ types, opcode };
+use crate::blocking;
+use crate::handle::Handle;
+use crate::sqe::{ RawEntry, IORING_OP_WAITID };
+use crate::actions::{ action, PushError };
+use crate::actions::fs::close_detached;
+
+
+/// A process builder, see [`std::process::Command`].
+///
+/// Piped stdio of the spawned child can be used with
+/// [`read_buf`](crate::actions::io::read_buf) and [`write_buf`](crate::actions::io::write_buf).
+pub struct Command {
+    inner: process::Command
+}
+
+/// A spawned child process.
+pub struct Child<H: Handle> {
+    handle: H,
+    child: process::Child,
+    status: Option<ExitStatus>,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>
+}
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        Command { inner: process::Command::new(program) }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Command {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Command {
+        self.inner.env_remove(key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.inner.env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    #[inline]
+    pub fn as_std_mut(&mut self) -> &mut process::Command {
+        &mut self.inner
+    }
+
+    pub fn spawn<H: Handle>(&mut self, handle: H) -> io::Result<Child<H>> {
+        let mut child = self.inner.spawn()?;
+
+        Ok(Child {
+            handle,
+            stdin: child.stdin.take(),
+            stdout: child.stdout.take(),
+            stderr: child.stderr.take(),
+            status: None,
+            child
+        })
+    }
+}
+
+impl From<process::Command> for Command {
+    #[inline]
+    fn from(inner: process::Command) -> Command {
+        Command { inner }
+    }
+}
+
+impl<H: Handle> Child<H> {
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Send `SIGKILL` to the child, unless it has already been waited on.
+    pub fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+
+        self.child.kill()
+    }
+
+    /// Wait for the child to exit, closing its stdin first.
+    ///
+    /// Uses `IORING_OP_WAITID`, falling back to polling a pidfd,
+    /// or to a blocking `waitpid(2)` if the kernel has neither.
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        close_pipe(&self.handle, self.stdin.take());
+
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+
+        let status = match self.waitid().await {
+            Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => match pidfd_open(self.id()) {
+                Ok(pidfd) => self.wait_pidfd(pidfd).await?,
+                Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS) => {
+                    let pid = self.id() as libc::pid_t;
+
+                    blocking::spawn(move || {
+                        let mut status = 0;
+
+                        loop {
+                            if unsafe { libc::waitpid(pid, &mut status, 0) } != -1 {
+                                break Ok(ExitStatus::from_raw(status));
+                            }
+
+                            let err = io::Error::last_os_error();
+                            if err.kind() != io::ErrorKind::Interrupted {
+                                break Err(err);
+                            }
+                        }
+                    }).await?
+                },
+                Err(err) => return Err(err)
+            },
+            ret => ret?
+        };
+
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    async fn waitid(&self) -> io::Result<ExitStatus> {
+        let info = Box::new(unsafe { mem::zeroed::<libc::siginfo_t>() });
+        let waitid_e = RawEntry {
+            opcode: IORING_OP_WAITID,
+            fd: self.id() as _,
+            len: libc::P_PID as _,
+            file_index: libc::WEXITED as _,
+            off: &*info as *const libc::siginfo_t as _,
+            ..Default::default()
+        }
+            .build();
+
+        let (info, cqe) = unsafe {
+            action(&self.handle, info, waitid_e)
+                .map_err(PushError::into_error)?.await
+        };
+
+        let ret = cqe.result();
+        if ret >= 0 {
+            Ok(exit_status(&info))
+        } else {
+            Err(io::Error::from_raw_os_error(-ret))
+        }
+    }
+
+    async fn wait_pidfd(&self, pidfd: OwnedFd) -> io::Result<ExitStatus> {
+        let poll_e = opcode::PollAdd::new(types::Fd(pidfd.as_raw_fd()), libc::POLLIN as _)
+            .build();
+
+        let (pidfd, cqe) = unsafe {
+            action(&self.handle, pidfd, poll_e)
+                .map_err(PushError::into_error)?.await
+        };
+
+        let ret = cqe.result();
+        if ret < 0 {
+            return Err(io::Error::from_raw_os_error(-ret));
+        }
+
+        // The child has exited, so this does not block.
+        let mut info = unsafe { mem::zeroed::<libc::siginfo_t>() };
+        let ret = unsafe {
+            libc::waitid(libc::P_PIDFD, pidfd.as_raw_fd() as _, &mut info, libc::WEXITED)
+        };
+
+        if ret == 0 {
+            Ok(exit_status(&info))
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+impl<H: Handle> Drop for Child<H> {
+    /// Pipes still held by the child are closed through the ring.
+    fn drop(&mut self) {
+        close_pipe(&self.handle, self.stdin.take());
+        close_pipe(&self.handle, self.stdout.take());
+        close_pipe(&self.handle, self.stderr.take());
+    }
+}
+
+#[inline]
+fn close_pipe<H: Handle, T: IntoRawFd>(handle: &H, pipe: Option<T>) {
+    if let Some(pipe) = pipe {
+        close_detached(handle, pipe.into_raw_fd());
+    }
+}
+
+fn pidfd_open(pid: u32) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+
+    if fd >= 0 {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as _) })
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Convert a `waitid` result to the `waitpid` status encoding.
+fn exit_status(info: &libc::siginfo_t) -> ExitStatus {
+    let status = unsafe { info.si_status() };
+
+    let raw = match info.si_code {
+        libc::CLD_EXITED => (status & 0xff) << 8,
+        libc::CLD_KILLED => status & 0x7f,
+        libc::CLD_DUMPED => (status & 0x7f) | 0x80,
+        _ => status
+    };
+
+    ExitStatus::from_raw(raw)
+}
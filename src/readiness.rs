@@ -0,0 +1,145 @@
+use std::io;
+use std::cell::Cell;
+use std::os::unix::io::{ AsRawFd, RawFd };
+use io_uring::{ types, opcode };
+use crate::handle::Handle;
+use crate::actions::{ action, PushError };
+use crate::actions::poll::{ PollFlags, poll_remove };
+
+
+/// Readiness notifications for a non-blocking fd.
+///
+/// For fds that must be driven by their own non-blocking syscalls,
+/// such as inotify, netlink or fds owned by a C library.
+/// Readiness is cached until an operation reports `WouldBlock`
+/// through [`try_io`](Readiness::try_io).
+pub struct Readiness<H: Handle, T: AsRawFd> {
+    handle: H,
+    inner: T,
+    ready: Cell<PollFlags>
+}
+
+/// Removes the poll request if the waiting future is dropped.
+struct Armed<'a, H: Handle> {
+    handle: &'a H,
+    user_data: u64,
+    done: bool
+}
+
+impl<H: Handle, T: AsRawFd> Readiness<H, T> {
+    /// The fd should be in non-blocking mode.
+    pub fn new(handle: H, inner: T) -> Readiness<H, T> {
+        Readiness {
+            handle,
+            inner,
+            ready: Cell::new(PollFlags::empty())
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Wait until the fd is readable.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.ready(PollFlags::IN).await.map(drop)
+    }
+
+    /// Wait until the fd is writable.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.ready(PollFlags::OUT).await.map(drop)
+    }
+
+    /// Wait until the fd is ready for any of `interest`, returns the ready events.
+    ///
+    /// Returns at once if readiness is already cached.
+    pub async fn ready(&self, interest: PollFlags) -> io::Result<PollFlags> {
+        let ready = self.ready.get() & (interest | PollFlags::ERR | PollFlags::HUP);
+        if !ready.is_empty() {
+            return Ok(ready);
+        }
+
+        let poll_e = opcode::PollAdd::new(types::Fd(self.inner.as_raw_fd()), interest.bits())
+            .build();
+
+        let action = unsafe {
+            action(&self.handle, (), poll_e)
+                .map_err(PushError::into_error)?
+        };
+
+        let mut armed = Armed {
+            handle: &self.handle,
+            user_data: action.user_data(),
+            done: false
+        };
+        let (_, cqe) = action.await;
+        armed.done = true;
+
+        let ret = cqe.result();
+        if ret >= 0 {
+            let ready = PollFlags::from_bits_truncate(ret as _);
+            self.ready.set(self.ready.get() | ready);
+            Ok(ready)
+        } else {
+            Err(io::Error::from_raw_os_error(-ret))
+        }
+    }
+
+    /// Clear the cached readiness for `interest`.
+    #[inline]
+    pub fn clear_ready(&self, interest: PollFlags) {
+        self.ready.set(self.ready.get() - interest);
+    }
+
+    /// Run a non-blocking operation.
+    ///
+    /// If it fails with `WouldBlock`, the readiness for `interest` is cleared,
+    /// so that the next wait goes to the kernel again.
+    pub fn try_io<R>(&self, interest: PollFlags, f: impl FnOnce(&T) -> io::Result<R>) -> io::Result<R> {
+        match f(&self.inner) {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.clear_ready(interest);
+                Err(io::ErrorKind::WouldBlock.into())
+            },
+            ret => ret
+        }
+    }
+
+    /// Wait for readiness and run `f` until it does not report `WouldBlock`.
+    pub async fn async_io<R>(&self, interest: PollFlags, mut f: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            self.ready(interest).await?;
+
+            match self.try_io(interest, &mut f) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => (),
+                ret => return ret
+            }
+        }
+    }
+}
+
+impl<H: Handle, T: AsRawFd> AsRawFd for Readiness<H, T> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<H: Handle> Drop for Armed<'_, H> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = poll_remove(self.handle, self.user_data);
+        }
+    }
+}
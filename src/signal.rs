@@ -0,0 +1,213 @@
+//! Signal handling through `signalfd(2)`.
+
+use std::{ io, mem, ptr };
+use std::pin::Pin;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::future::Future;
+use std::task::{ Context, Poll };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use futures_core::Stream;
+use io_uring::{ types, opcode };
+use crate::handle::Handle;
+use crate::actions::{ self, action, Action };
+
+
+/// A stream of signals received by the process.
+///
+/// The signals are blocked in the calling thread, so that they are only
+/// delivered through the signalfd. They should be blocked in every thread of
+/// the process, so create it before spawning threads, which inherit the mask.
+/// Threads of the blocking pool always block asynchronous signals.
+///
+/// A signal is unblocked again when the last `Signals` of the thread that
+/// blocked it is dropped, unless it was blocked before the first of them.
+/// Since the mask is per thread, it cannot be sent to another thread.
+/// The signalfd is closed through the ring.
+pub struct Signals<H: Handle> {
+    handle: H,
+    fd: RawFd,
+    set: libc::sigset_t,
+    state: Option<Action<Box<libc::signalfd_siginfo>>>,
+    _thread: PhantomData<*const ()>
+}
+
+impl<H: Handle> Signals<H> {
+    pub fn new(handle: H, signals: &[libc::c_int]) -> io::Result<Signals<H>> {
+        let mut set = unsafe { mem::zeroed::<libc::sigset_t>() };
+        let mut old = unsafe { mem::zeroed::<libc::sigset_t>() };
+
+        unsafe {
+            libc::sigemptyset(&mut set);
+
+            for &signal in signals {
+                if libc::sigaddset(&mut set, signal) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            let ret = libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret));
+            }
+        }
+
+        acquire(&set, &old);
+
+        let fd = unsafe { libc::signalfd(-1, &set, libc::SFD_CLOEXEC) };
+        if fd == -1 {
+            let err = io::Error::last_os_error();
+            release(&set);
+            return Err(err);
+        }
+
+        Ok(Signals {
+            handle,
+            fd,
+            set,
+            state: None,
+            _thread: PhantomData
+        })
+    }
+
+    /// Waits for the next signal.
+    pub async fn recv(&mut self) -> io::Result<libc::signalfd_siginfo>
+    where H: Unpin
+    {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+    }
+}
+
+impl<H: Handle + Unpin> Stream for Signals<H> {
+    type Item = io::Result<libc::signalfd_siginfo>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                None => {
+                    let mut buf = Box::new(unsafe { mem::zeroed::<libc::signalfd_siginfo>() });
+                    let read_e = opcode::Read::new(
+                        types::Fd(this.fd),
+                        (&mut *buf as *mut libc::signalfd_siginfo).cast(),
+                        mem::size_of::<libc::signalfd_siginfo>() as _
+                    )
+                        .build();
+
+                    let action = match unsafe { action(&this.handle, buf, read_e) } {
+                        Ok(action) => action,
+                        Err(err) => return Poll::Ready(Some(Err(err.into_error())))
+                    };
+
+                    this.state = Some(action);
+                },
+                Some(action) => {
+                    let (buf, cqe) = futures_core::ready!(Pin::new(action).poll(cx));
+                    this.state = None;
+
+                    let ret = cqe.result();
+                    let ret = if ret as usize == mem::size_of::<libc::signalfd_siginfo>() {
+                        Ok(*buf)
+                    } else if ret < 0 {
+                        Err(io::Error::from_raw_os_error(-ret))
+                    } else {
+                        Err(io::ErrorKind::UnexpectedEof.into())
+                    };
+
+                    return Poll::Ready(Some(ret));
+                }
+            }
+        }
+    }
+}
+
+impl<H: Handle> AsRawFd for Signals<H> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl<H: Handle> Drop for Signals<H> {
+    fn drop(&mut self) {
+        // Otherwise the pending read would still consume a signal.
+        if let Some(action) = self.state.take() {
+            let _ = actions::cancel(&self.handle, action);
+        }
+
+        actions::fs::close_detached(&self.handle, self.fd);
+        release(&self.set);
+    }
+}
+
+/// Signal numbers go up to `SIGRTMAX`, which is 64 on Linux.
+const NSIG: usize = 65;
+
+thread_local!{
+    /// Per signal, the number of live `Signals` of this thread that block it,
+    /// and whether one of them blocked it rather than the application.
+    static BLOCKED: RefCell<[(u32, bool); NSIG]> = const { RefCell::new([(0, false); NSIG]) };
+}
+
+/// Count the signals of `set` as blocked, `old` is the mask before blocking them.
+fn acquire(set: &libc::sigset_t, old: &libc::sigset_t) {
+    BLOCKED.with(|blocked| {
+        let mut blocked = blocked.borrow_mut();
+
+        for (signal, (count, owned)) in blocked.iter_mut().enumerate().skip(1) {
+            if unsafe { libc::sigismember(set, signal as _) } != 1 {
+                continue
+            }
+
+            if *count == 0 {
+                *owned = unsafe { libc::sigismember(old, signal as _) } == 0;
+            }
+
+            *count += 1;
+        }
+    });
+}
+
+/// Undo [`acquire`], unblocking the signals that are no longer used.
+fn release(set: &libc::sigset_t) {
+    let mut unblock = unsafe { mem::zeroed::<libc::sigset_t>() };
+
+    BLOCKED.with(|blocked| {
+        let mut blocked = blocked.borrow_mut();
+
+        unsafe {
+            libc::sigemptyset(&mut unblock);
+        }
+
+        for (signal, (count, owned)) in blocked.iter_mut().enumerate().skip(1) {
+            if unsafe { libc::sigismember(set, signal as _) } != 1 {
+                continue
+            }
+
+            *count -= 1;
+
+            if *count == 0 && mem::take(owned) {
+                unsafe {
+                    libc::sigaddset(&mut unblock, signal as _);
+                }
+            }
+        }
+    });
+
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &unblock, ptr::null_mut());
+    }
+}
+
+/// Waits for `SIGINT`.
+///
+/// `SIGINT` is unblocked again once it returns, unless another [`Signals`] still uses it.
+pub async fn ctrl_c<H: Handle + Unpin>(handle: H) -> io::Result<()> {
+    Signals::new(handle, &[libc::SIGINT])?
+        .recv()
+        .await
+        .map(drop)
+}
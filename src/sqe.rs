@@ -0,0 +1,59 @@
+//! Raw submission and completion entries, for opcodes and fields that `io-uring` does not expose yet.
+
+use std::mem;
+use io_uring::{ squeue, cqueue };
+
+
+pub const IORING_OP_WAITID: u8 = 50;
+pub const IORING_OP_FTRUNCATE: u8 = 55;
+
+/// `IORING_TIMEOUT_MULTISHOT`, since Linux 6.4.
+pub const IORING_TIMEOUT_MULTISHOT: u32 = 1 << 6;
+
+pub const IORING_CQE_F_MORE: u32 = 1 << 1;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct RawEntry {
+    pub opcode: u8,
+    pub flags: u8,
+    pub ioprio: u16,
+    pub fd: i32,
+    pub off: u64,
+    pub addr: u64,
+    pub len: u32,
+    pub op_flags: u32,
+    pub user_data: u64,
+    pub buf_index: u16,
+    pub personality: u16,
+    pub file_index: u32,
+    pub addr3: u64,
+    pub pad: u64
+}
+
+impl RawEntry {
+    #[inline]
+    pub fn from_entry(entry: squeue::Entry) -> RawEntry {
+        unsafe { mem::transmute(entry) }
+    }
+
+    #[inline]
+    pub fn build(self) -> squeue::Entry {
+        unsafe { mem::transmute(self) }
+    }
+}
+
+/// A completion made up by the proactor, for requests it handles itself.
+#[repr(C)]
+pub struct RawCqe {
+    pub user_data: u64,
+    pub res: i32,
+    pub flags: u32
+}
+
+impl RawCqe {
+    #[inline]
+    pub fn build(self) -> cqueue::Entry {
+        unsafe { mem::transmute(self) }
+    }
+}
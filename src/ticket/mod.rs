@@ -1,4 +1,5 @@
 pub mod oneshot;
+pub mod multishot;
 
 use std::ptr;
 use std::pin::Pin;
@@ -0,0 +1,98 @@
+//! Ticket for multishot requests, which may post many completions.
+
+use std::ptr;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::collections::VecDeque;
+use std::task::{ Context, Waker, Poll };
+use futures_core::Stream;
+use io_uring::cqueue;
+
+
+pub struct MultiTicket(Arc<Mutex<Inner>>);
+
+pub struct MultiTicketStream(Arc<Mutex<Inner>>);
+
+struct Inner {
+    queue: VecDeque<cqueue::Entry>,
+    waker: Option<Waker>,
+    done: bool
+}
+
+pub fn channel() -> (MultiTicket, MultiTicketStream) {
+    let inner = Arc::new(Mutex::new(Inner {
+        queue: VecDeque::new(),
+        waker: None,
+        done: false
+    }));
+
+    (MultiTicket(Arc::clone(&inner)), MultiTicketStream(inner))
+}
+
+#[inline]
+fn lock(inner: &Mutex<Inner>) -> MutexGuard<'_, Inner> {
+    inner.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl MultiTicket {
+    #[inline]
+    pub fn into_raw(self) -> ptr::NonNull<MultiTicket> {
+        let ptr = Arc::into_raw(self.0) as *mut Mutex<Inner>;
+        unsafe { ptr::NonNull::new_unchecked(ptr).cast() }
+    }
+
+    /// # Safety
+    ///
+    /// Constructs an `MultiTicket` from a raw pointer.
+    #[inline]
+    pub unsafe fn from_raw(ptr: ptr::NonNull<MultiTicket>) -> MultiTicket {
+        MultiTicket(Arc::from_raw(ptr.cast::<Mutex<Inner>>().as_ptr()))
+    }
+
+    /// Deliver a completion, `more` tells whether the request is still alive.
+    pub fn send(&self, entry: cqueue::Entry, more: bool) {
+        let mut inner = lock(&self.0);
+        inner.queue.push_back(entry);
+        inner.done = !more;
+
+        if let Some(waker) = inner.waker.take() {
+            drop(inner);
+            waker.wake();
+        }
+    }
+}
+
+impl MultiTicketStream {
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        let inner = lock(&self.0);
+        inner.done && inner.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> ptr::NonNull<MultiTicketStream> {
+        let ptr = Arc::as_ptr(&self.0) as *mut Mutex<Inner>;
+        unsafe { ptr::NonNull::new_unchecked(ptr).cast() }
+    }
+}
+
+impl Stream for MultiTicketStream {
+    type Item = cqueue::Entry;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut inner = lock(&self.0);
+
+        if let Some(entry) = inner.queue.pop_front() {
+            Poll::Ready(Some(entry))
+        } else if inner.done {
+            Poll::Ready(None)
+        } else {
+            match inner.waker {
+                Some(ref waker) if waker.will_wake(cx.waker()) => (),
+                _ => inner.waker = Some(cx.waker().clone())
+            }
+
+            Poll::Pending
+        }
+    }
+}
@@ -193,7 +193,7 @@ impl<T> Drop for InlineRc<T> {
         // check reference count
         if state & CLOSED == CLOSED {
             unsafe {
-                Box::from_raw(self.0.as_ptr());
+                drop(Box::from_raw(self.0.as_ptr()));
             }
         }
     }
@@ -0,0 +1,351 @@
+use std::{ io, mem };
+use std::pin::Pin;
+use std::future::Future;
+use std::time::{ Duration, Instant };
+use std::task::{ Context, Poll };
+use futures_core::Stream;
+use io_uring::{ types, opcode };
+use crate::EMPTY_TOKEN;
+use crate::sqe::{ RawEntry, IORING_TIMEOUT_MULTISHOT };
+use crate::handle::Handle;
+use crate::actions::{ action, action_multi, Action, MultiAction, PushError };
+
+
+/// Future returned by [`sleep`] and [`sleep_until`].
+///
+/// The timeout is armed on first poll. If the proactor has its timer wheel
+/// enabled, the timeout is kept there instead of in the kernel.
+pub struct Sleep<H: Handle> {
+    handle: H,
+    deadline: Instant,
+    state: SleepState,
+    cancelled: bool,
+    // In-flight updates, each holds its timespec until the kernel has read it.
+    updates: Vec<Action<Box<types::Timespec>>>
+}
+
+enum SleepState {
+    Idle,
+    Armed(Action<Box<types::Timespec>>),
+    // Holds the errno if it failed.
+    Done(Option<i32>)
+}
+
+/// Waits until `dur` has elapsed.
+pub fn sleep<H: Handle>(handle: H, dur: Duration) -> Sleep<H> {
+    sleep_until(handle, Instant::now() + dur)
+}
+
+/// Waits until `deadline` is reached.
+///
+/// Uses an absolute `CLOCK_MONOTONIC` timeout, so it does not drift
+/// however late the future is first polled.
+pub fn sleep_until<H: Handle>(handle: H, deadline: Instant) -> Sleep<H> {
+    Sleep {
+        handle,
+        deadline,
+        state: SleepState::Idle,
+        cancelled: false,
+        updates: Vec::new()
+    }
+}
+
+impl<H: Handle> Sleep<H> {
+    #[inline]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    #[inline]
+    pub fn is_elapsed(&self) -> bool {
+        matches!(self.state, SleepState::Done(_))
+    }
+
+    /// Reset the deadline, even if the sleep has already completed or was cancelled.
+    ///
+    /// An armed timeout is modified in place with `IORING_TIMEOUT_UPDATE`
+    /// instead of being cancelled and submitted again.
+    pub fn reset(&mut self, deadline: Instant) -> io::Result<()> {
+        self.deadline = deadline;
+        self.cancelled = false;
+        release_completed(&mut self.updates);
+
+        match &self.state {
+            SleepState::Armed(timeout) if !timeout.is_completed() => {
+                let timespec = Box::new(abs_timespec(deadline));
+                let update_e = RawEntry {
+                    opcode: opcode::TimeoutRemove::CODE,
+                    fd: -1,
+                    addr: timeout.user_data(),
+                    off: &*timespec as *const types::Timespec as _,
+                    op_flags: (types::TimeoutFlags::UPDATE | types::TimeoutFlags::ABS).bits(),
+                    ..Default::default()
+                }
+                    .build();
+
+                let update = unsafe {
+                    action(&self.handle, timespec, update_e)
+                        .map_err(PushError::into_error)?
+                };
+                self.updates.push(update);
+            },
+            SleepState::Armed(_) => (),
+            SleepState::Idle | SleepState::Done(_) => self.state = SleepState::Idle
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the sleep, it will resolve with `ECANCELED`
+    /// unless the timeout has already fired.
+    pub fn cancel(&mut self) -> io::Result<()> {
+        self.cancelled = true;
+
+        match &self.state {
+            SleepState::Armed(action) if !action.is_completed() =>
+                timeout_remove(&self.handle, action.user_data())?,
+            SleepState::Armed(_) => (),
+            SleepState::Idle => self.state = SleepState::Done(Some(libc::ECANCELED)),
+            SleepState::Done(_) => ()
+        }
+
+        Ok(())
+    }
+}
+
+impl<H: Handle + Unpin> Future for Sleep<H> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                SleepState::Idle => {
+                    let timespec = Box::new(abs_timespec(this.deadline));
+                    let timeout_e = opcode::Timeout::new(&*timespec)
+                        .flags(types::TimeoutFlags::ABS)
+                        .build();
+
+                    let action = unsafe {
+                        action(&this.handle, timespec, timeout_e)
+                            .map_err(PushError::into_error)?
+                    };
+
+                    this.state = SleepState::Armed(action);
+                },
+                SleepState::Armed(action) => {
+                    let (_, cqe) = futures_core::ready!(Pin::new(action).poll(cx));
+                    let ret = cqe.result();
+
+                    this.state = if !this.cancelled
+                        && (ret == -libc::ETIME || ret == -libc::ECANCELED)
+                        && Instant::now() < this.deadline
+                    {
+                        // The timeout fired or was removed before a later reset reached the kernel.
+                        SleepState::Idle
+                    } else if ret >= 0 || ret == -libc::ETIME {
+                        SleepState::Done(None)
+                    } else {
+                        SleepState::Done(Some(-ret))
+                    };
+                },
+                SleepState::Done(None) => return Poll::Ready(Ok(())),
+                SleepState::Done(Some(errno)) =>
+                    return Poll::Ready(Err(io::Error::from_raw_os_error(*errno)))
+            }
+        }
+    }
+}
+
+impl<H: Handle> Drop for Sleep<H> {
+    fn drop(&mut self) {
+        release_completed(&mut self.updates);
+
+        if let SleepState::Armed(action) = mem::replace(&mut self.state, SleepState::Idle) {
+            if let Err(action) = action.into_completed() {
+                let _ = timeout_remove(&self.handle, action.user_data());
+            }
+        }
+    }
+}
+
+/// A stream that yields at a fixed period.
+///
+/// The first tick completes after one period. Uses a multishot timeout
+/// where the kernel supports it, so that ticks do not need re-arming.
+pub struct Interval<H: Handle> {
+    handle: H,
+    period: Duration,
+    next: Instant,
+    state: IntervalState
+}
+
+enum IntervalState {
+    Idle,
+    Multishot(MultiAction<Box<types::Timespec>>),
+    Oneshot(Action<Box<types::Timespec>>)
+}
+
+pub fn interval<H: Handle>(handle: H, period: Duration) -> Interval<H> {
+    assert!(period > Duration::from_secs(0), "`period` must be non-zero.");
+
+    Interval {
+        handle,
+        period,
+        next: Instant::now() + period,
+        state: IntervalState::Idle
+    }
+}
+
+impl<H: Handle> Interval<H> {
+    #[inline]
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Waits for the next tick, returning the instant it was scheduled for.
+    pub async fn tick(&mut self) -> io::Result<Instant>
+    where H: Unpin
+    {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+    }
+}
+
+impl<H: Handle + Unpin> Stream for Interval<H> {
+    type Item = io::Result<Instant>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                IntervalState::Idle => {
+                    let timespec = Box::new(timespec(this.period));
+                    let timeout_e = opcode::Timeout::new(&*timespec)
+                        .flags(unsafe {
+                            types::TimeoutFlags::from_bits_unchecked(IORING_TIMEOUT_MULTISHOT)
+                        })
+                        .build();
+
+                    let action = match unsafe { action_multi(&this.handle, timespec, timeout_e) } {
+                        Ok(action) => action,
+                        Err(err) => return Poll::Ready(Some(Err(err.into_error())))
+                    };
+
+                    this.state = IntervalState::Multishot(action);
+                },
+                IntervalState::Multishot(action) => {
+                    let cqe = match futures_core::ready!(Pin::new(action).poll_next(cx)) {
+                        Some(cqe) => cqe,
+                        None => return Poll::Ready(None)
+                    };
+
+                    match cqe.result() {
+                        ret if ret >= 0 || ret == -libc::ETIME => {
+                            let now = this.next;
+                            this.next += this.period;
+                            return Poll::Ready(Some(Ok(now)));
+                        },
+
+                        // Older kernels do not know about multishot timeouts.
+                        ret if ret == -libc::EINVAL => if let Err(err) = this.arm_oneshot() {
+                            return Poll::Ready(Some(Err(err)));
+                        },
+                        ret => return Poll::Ready(Some(Err(io::Error::from_raw_os_error(-ret))))
+                    }
+                },
+                IntervalState::Oneshot(action) => {
+                    let (_, cqe) = futures_core::ready!(Pin::new(action).poll(cx));
+                    let now = this.next;
+                    this.next += this.period;
+
+                    let ret = cqe.result();
+                    let ret = if ret >= 0 || ret == -libc::ETIME {
+                        this.arm_oneshot().map(|_| now)
+                    } else {
+                        Err(io::Error::from_raw_os_error(-ret))
+                    };
+
+                    return Poll::Ready(Some(ret));
+                },
+            }
+        }
+    }
+}
+
+impl<H: Handle> Interval<H> {
+    fn arm_oneshot(&mut self) -> io::Result<()> {
+        let timespec = Box::new(abs_timespec(self.next));
+        let timeout_e = opcode::Timeout::new(&*timespec)
+            .flags(types::TimeoutFlags::ABS)
+            .build();
+
+        let action = unsafe {
+            action(&self.handle, timespec, timeout_e)
+                .map_err(PushError::into_error)?
+        };
+        self.state = IntervalState::Oneshot(action);
+
+        Ok(())
+    }
+}
+
+impl<H: Handle> Drop for Interval<H> {
+    fn drop(&mut self) {
+        let user_data = match &self.state {
+            IntervalState::Idle => return,
+            IntervalState::Multishot(action) if !action.is_done() => action.user_data(),
+            IntervalState::Oneshot(action) if !action.is_completed() => action.user_data(),
+            _ => return
+        };
+
+        let _ = timeout_remove(&self.handle, user_data);
+    }
+}
+
+/// Drop the timespecs of updates that the kernel is done with.
+fn release_completed(updates: &mut Vec<Action<Box<types::Timespec>>>) {
+    *updates = mem::take(updates)
+        .into_iter()
+        .filter_map(|update| update.into_completed().err())
+        .collect();
+}
+
+fn timeout_remove<H: Handle>(handle: H, user_data: u64) -> io::Result<()> {
+    let remove_e = opcode::TimeoutRemove::new(user_data)
+        .build()
+        .user_data(EMPTY_TOKEN);
+
+    unsafe {
+        handle.push(&remove_e)
+    }
+}
+
+/// Convert an `Instant` to an absolute `CLOCK_MONOTONIC` timespec.
+pub(crate) fn abs_timespec(deadline: Instant) -> types::Timespec {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    let now = Instant::now();
+
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+
+    let clock = Duration::new(ts.tv_sec as _, ts.tv_nsec as _);
+    let clock = if deadline > now {
+        clock + (deadline - now)
+    } else {
+        clock.checked_sub(now - deadline).unwrap_or_default()
+    };
+
+    timespec(clock)
+}
+
+#[inline]
+fn timespec(dur: Duration) -> types::Timespec {
+    types::Timespec::new()
+        .sec(dur.as_secs())
+        .nsec(dur.subsec_nanos())
+}
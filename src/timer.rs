@@ -0,0 +1,620 @@
+//! Hierarchical timer wheel.
+//!
+//! Six levels of 64 slots with millisecond ticks, so timers up to about two years
+//! away are tracked with constant time insertion and removal.
+//! Each slot is an intrusive list threaded through the entry slab.
+//!
+//! Timeouts pushed through the proactor's handles are taken out of the way
+//! to the kernel and kept here, along with removes and updates of them.
+
+use std::cell::{ Cell, RefCell };
+use std::collections::HashMap;
+use std::time::{ Duration, Instant };
+use io_uring::{ types, opcode, squeue };
+use crate::WHEEL_TOKEN;
+use crate::sqe::{ RawEntry, IORING_TIMEOUT_MULTISHOT };
+
+
+const LEVELS: usize = 6;
+const SLOTS: usize = 64;
+const SLOT_BITS: usize = 6;
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS);
+
+/// Timer wheel shared by a [`Proactor`](crate::Proactor) and its handles.
+///
+/// All timers are multiplexed onto a single kernel timeout,
+/// armed for the nearest deadline. Timers are keyed by the user data
+/// of the timeout entry they stand in for.
+pub(crate) struct TimerWheel {
+    enabled: Cell<bool>,
+    // Deadline of the kernel timeout, if one is in flight.
+    armed: Cell<Option<Instant>>,
+    wheel: RefCell<Wheel>
+}
+
+struct Wheel {
+    start: Instant,
+    elapsed: u64,
+    levels: [Level; LEVELS],
+    entries: Vec<Entry>,
+    free: Vec<usize>,
+    keys: HashMap<u64, usize>,
+    // Expired on insertion, fired on the next call to `fire`.
+    due: Vec<usize>
+}
+
+#[derive(Clone, Copy)]
+struct Level {
+    occupied: u64,
+    heads: [Option<usize>; SLOTS]
+}
+
+struct Entry {
+    deadline: u64,
+    // Zero for a oneshot timer.
+    period: u64,
+    user_data: u64,
+    state: State,
+    prev: Option<usize>,
+    next: Option<usize>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Free,
+    Pending { level: usize, slot: usize },
+    Due
+}
+
+impl TimerWheel {
+    pub(crate) fn new() -> TimerWheel {
+        TimerWheel {
+            enabled: Cell::new(false),
+            armed: Cell::new(None),
+            wheel: RefCell::new(Wheel {
+                start: Instant::now(),
+                elapsed: 0,
+                levels: [Level { occupied: 0, heads: [None; SLOTS] }; LEVELS],
+                entries: Vec::new(),
+                free: Vec::new(),
+                keys: HashMap::new(),
+                due: Vec::new()
+            })
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    #[inline]
+    pub(crate) fn enable(&self) {
+        self.enabled.set(true);
+    }
+
+    #[inline]
+    pub(crate) fn armed(&self) -> Option<Instant> {
+        self.armed.get()
+    }
+
+    #[inline]
+    pub(crate) fn set_armed(&self, deadline: Option<Instant>) {
+        self.armed.set(deadline);
+    }
+
+    /// Keep a timeout, or a remove or update of one, in the wheel instead of the kernel.
+    ///
+    /// Returns the completions to deliver right away, or `None` if the entry
+    /// has to go to the kernel. Only standalone timeouts without a count or a clock
+    /// other than `CLOCK_MONOTONIC` are taken, anything linked or flagged is not.
+    ///
+    /// # Safety
+    ///
+    /// The timespec of the entry must be valid, as for pushing it.
+    pub(crate) unsafe fn take(&self, entry: &squeue::Entry) -> Option<Vec<(u64, i32)>> {
+        if !self.is_enabled() {
+            return None
+        }
+
+        let raw = RawEntry::from_entry(entry.clone());
+
+        if raw.flags != 0 {
+            return None
+        }
+
+        let abs = types::TimeoutFlags::ABS.bits();
+        let update = types::TimeoutFlags::UPDATE.bits();
+
+        match raw.opcode {
+            // Tokens are shared by many requests, they cannot key a timer.
+            opcode::Timeout::CODE if raw.off == 0 && raw.user_data > WHEEL_TOKEN => {
+                let ts = read_timespec(raw.addr);
+
+                match raw.op_flags {
+                    0 => self.insert(raw.user_data, Instant::now() + ts, None),
+                    flags if flags == abs => self.insert(raw.user_data, from_monotonic(ts), None),
+                    IORING_TIMEOUT_MULTISHOT => self.insert(raw.user_data, Instant::now() + ts, Some(ts)),
+                    _ => return None
+                }
+
+                Some(Vec::new())
+            },
+            opcode::TimeoutRemove::CODE => match raw.op_flags {
+                0 if self.remove(raw.addr) => Some(vec![(raw.addr, -libc::ECANCELED), (raw.user_data, 0)]),
+                flags if flags == update || flags == update | abs => {
+                    let ts = read_timespec(raw.off);
+                    let deadline = if flags & abs != 0 {
+                        from_monotonic(ts)
+                    } else {
+                        Instant::now() + ts
+                    };
+
+                    if self.update(raw.addr, deadline) {
+                        Some(vec![(raw.user_data, 0)])
+                    } else {
+                        None
+                    }
+                },
+                _ => None
+            },
+            opcode::AsyncCancel::CODE if raw.op_flags == 0 && self.remove(raw.addr) =>
+                Some(vec![(raw.addr, -libc::ECANCELED), (raw.user_data, 0)]),
+            _ => None
+        }
+    }
+
+    /// Register a timer, it fires again every `period` if there is one.
+    ///
+    /// A timer already registered for `user_data` is replaced.
+    pub(crate) fn insert(&self, user_data: u64, deadline: Instant, period: Option<Duration>) {
+        let mut wheel = self.wheel.borrow_mut();
+
+        if let Some(key) = wheel.keys.get(&user_data).copied() {
+            wheel.free(key);
+        }
+
+        let entry = Entry {
+            deadline: wheel.tick(deadline),
+            period: period.map_or(0, |period| wheel.ticks(period).max(1)),
+            user_data,
+            state: State::Free,
+            prev: None,
+            next: None
+        };
+
+        let key = match wheel.free.pop() {
+            Some(key) => {
+                wheel.entries[key] = entry;
+                key
+            },
+            None => {
+                wheel.entries.push(entry);
+                wheel.entries.len() - 1
+            }
+        };
+
+        wheel.keys.insert(user_data, key);
+        wheel.schedule(key);
+    }
+
+    /// Move the deadline of a registered timer, returns false if there is none.
+    pub(crate) fn update(&self, user_data: u64, deadline: Instant) -> bool {
+        let mut wheel = self.wheel.borrow_mut();
+        let key = match wheel.keys.get(&user_data) {
+            Some(&key) => key,
+            None => return false
+        };
+        let tick = wheel.tick(deadline);
+
+        wheel.unlink(key);
+        wheel.entries[key].deadline = tick;
+        wheel.schedule(key);
+        true
+    }
+
+    /// Unregister a timer, returns false if there is none.
+    pub(crate) fn remove(&self, user_data: u64) -> bool {
+        let mut wheel = self.wheel.borrow_mut();
+
+        match wheel.keys.get(&user_data) {
+            Some(&key) => {
+                wheel.free(key);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// The nearest deadline of all registered timers.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let wheel = self.wheel.borrow();
+
+        if !wheel.due.is_empty() {
+            return Some(wheel.start + Duration::from_millis(wheel.elapsed));
+        }
+
+        wheel.next_expiration()
+            .map(|(_, _, tick)| wheel.start + Duration::from_millis(tick))
+    }
+
+    /// Fire all timers whose deadline has passed, returns the user data of
+    /// each and whether it fires again.
+    ///
+    /// Oneshot timers are unregistered, periodic ones scheduled for their next period.
+    pub(crate) fn fire(&self, now: Instant) -> Vec<(u64, bool)> {
+        let mut wheel = self.wheel.borrow_mut();
+        let mut fired = Vec::new();
+        let now = wheel.tick_floor(now);
+
+        while let Some((level, slot, tick)) = wheel.next_expiration() {
+            if tick > now {
+                break
+            }
+
+            wheel.elapsed = tick;
+
+            let mut next = wheel.levels[level].heads[slot].take();
+            wheel.levels[level].occupied &= !(1 << slot);
+
+            while let Some(key) = next {
+                let entry = &mut wheel.entries[key];
+                next = entry.next.take();
+                entry.prev = None;
+                entry.state = State::Free;
+
+                if entry.deadline <= now {
+                    fired.push(key);
+                } else {
+                    // Cascade down to a lower level.
+                    wheel.schedule(key);
+                }
+            }
+        }
+
+        if now > wheel.elapsed {
+            wheel.elapsed = now;
+        }
+
+        let due = std::mem::take(&mut wheel.due);
+        fired.extend(due);
+
+        fired.into_iter()
+            .map(|key| {
+                let entry = &mut wheel.entries[key];
+                entry.state = State::Free;
+                let user_data = entry.user_data;
+
+                if entry.period == 0 {
+                    wheel.free(key);
+                    (user_data, false)
+                } else {
+                    entry.deadline += entry.period;
+
+                    // Skip the periods that were missed.
+                    if entry.deadline <= now {
+                        entry.deadline += (now - entry.deadline) / entry.period * entry.period + entry.period;
+                    }
+
+                    wheel.schedule(key);
+                    (user_data, true)
+                }
+            })
+            .collect()
+    }
+}
+
+impl Wheel {
+    /// Round up, a timer must never fire early.
+    fn tick(&self, deadline: Instant) -> u64 {
+        self.ticks(deadline.saturating_duration_since(self.start))
+    }
+
+    fn ticks(&self, dur: Duration) -> u64 {
+        let ms = dur.as_millis() as u64;
+
+        if dur > Duration::from_millis(ms) {
+            ms + 1
+        } else {
+            ms
+        }
+    }
+
+    fn tick_floor(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_millis() as u64
+    }
+
+    fn schedule(&mut self, key: usize) {
+        let elapsed = self.elapsed;
+        let entry = &mut self.entries[key];
+
+        // Already expired, it fires on the next call to `fire`.
+        if entry.deadline <= elapsed {
+            entry.state = State::Due;
+            self.due.push(key);
+            return
+        }
+
+        // Beyond the wheel, park it in the farthest slot. It is scheduled
+        // again from there when that slot expires, the deadline is kept.
+        let target = entry.deadline.min(elapsed + MAX_TICKS - 1);
+
+        let level = level_for(elapsed, target);
+        let slot = ((target >> (level * SLOT_BITS)) as usize) % SLOTS;
+        let head = self.levels[level].heads[slot].replace(key);
+
+        let entry = &mut self.entries[key];
+        entry.state = State::Pending { level, slot };
+        entry.prev = None;
+        entry.next = head;
+
+        if let Some(head) = head {
+            self.entries[head].prev = Some(key);
+        }
+
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    fn free(&mut self, key: usize) {
+        self.unlink(key);
+
+        let user_data = self.entries[key].user_data;
+        if self.keys.get(&user_data) == Some(&key) {
+            self.keys.remove(&user_data);
+        }
+
+        self.free.push(key);
+    }
+
+    fn unlink(&mut self, key: usize) {
+        let (level, slot) = match self.entries[key].state {
+            State::Pending { level, slot } => (level, slot),
+            State::Due => {
+                self.due.retain(|&due| due != key);
+                self.entries[key].state = State::Free;
+                return
+            },
+            State::Free => return
+        };
+
+        let prev = self.entries[key].prev.take();
+        let next = self.entries[key].next.take();
+
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None => self.levels[level].heads[slot] = next
+        }
+
+        if let Some(next) = next {
+            self.entries[next].prev = prev;
+        }
+
+        if self.levels[level].heads[slot].is_none() {
+            self.levels[level].occupied &= !(1 << slot);
+        }
+
+        self.entries[key].state = State::Free;
+    }
+
+    /// Lower levels always expire first, so the first occupied slot found wins.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        for (level, lv) in self.levels.iter().enumerate() {
+            if lv.occupied == 0 {
+                continue
+            }
+
+            let slot_range = 1u64 << (level * SLOT_BITS);
+            let level_range = slot_range << SLOT_BITS;
+            let now_slot = ((self.elapsed / slot_range) % SLOTS as u64) as u32;
+            let slot = (lv.occupied.rotate_right(now_slot).trailing_zeros() + now_slot) as usize % SLOTS;
+
+            let level_start = self.elapsed & !(level_range - 1);
+            let mut tick = level_start + slot as u64 * slot_range;
+
+            // The top level wraps around.
+            if tick + slot_range <= self.elapsed {
+                tick += level_range;
+            }
+
+            return Some((level, slot, tick.max(self.elapsed)));
+        }
+
+        None
+    }
+}
+
+/// Read a `__kernel_timespec`, the layout of `types::Timespec`.
+unsafe fn read_timespec(addr: u64) -> Duration {
+    let ts = *(addr as *const [i64; 2]);
+    Duration::new(ts[0].max(0) as u64, ts[1].clamp(0, 999_999_999) as u32)
+}
+
+/// The instant of an absolute `CLOCK_MONOTONIC` time, the reverse of `time::abs_timespec`.
+fn from_monotonic(clock: Duration) -> Instant {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    let now = Instant::now();
+
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+
+    let current = Duration::new(ts.tv_sec as _, ts.tv_nsec as _);
+
+    if clock > current {
+        now + (clock - current)
+    } else {
+        now.checked_sub(current - clock).unwrap_or(now)
+    }
+}
+
+fn level_for(elapsed: u64, deadline: u64) -> usize {
+    let mut masked = (elapsed ^ deadline) | (SLOTS as u64 - 1);
+
+    if masked >= MAX_TICKS {
+        masked = MAX_TICKS - 1;
+    }
+
+    let significant = 63 - masked.leading_zeros() as usize;
+    significant / SLOT_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn wheel() -> (TimerWheel, Instant) {
+        let timers = TimerWheel::new();
+        let start = timers.wheel.borrow().start;
+        (timers, start)
+    }
+
+    fn fired(timers: &TimerWheel, now: Instant) -> Vec<u64> {
+        timers.fire(now).into_iter().map(|(user_data, _)| user_data).collect()
+    }
+
+    #[test]
+    fn insert_and_fire() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 10 * MS, None);
+
+        assert_eq!(timers.next_deadline(), Some(start + 10 * MS));
+        assert!(fired(&timers, start + 9 * MS).is_empty());
+        assert_eq!(timers.fire(start + 10 * MS), [(1, false)]);
+        assert_eq!(timers.next_deadline(), None);
+        assert!(!timers.remove(1));
+
+        // A deadline that has passed fires on the next call.
+        timers.insert(2, start, None);
+        assert_eq!(timers.next_deadline(), Some(start + 10 * MS));
+        assert_eq!(fired(&timers, start + 10 * MS), [2]);
+    }
+
+    #[test]
+    fn fire_returns_every_timer() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 5 * MS, None);
+        timers.insert(2, start + 5 * MS, None);
+
+        let mut fired = fired(&timers, start + 5 * MS);
+        fired.sort_unstable();
+        assert_eq!(fired, [1, 2]);
+    }
+
+    #[test]
+    fn remove_frees_the_key() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 10 * MS, None);
+        timers.insert(2, start + 20 * MS, None);
+
+        assert!(timers.remove(1));
+        assert!(!timers.remove(1));
+        assert_eq!(timers.next_deadline(), Some(start + 20 * MS));
+        assert!(fired(&timers, start + 10 * MS).is_empty());
+
+        // The slot is reused.
+        timers.insert(3, start + 30 * MS, None);
+        assert_eq!(timers.wheel.borrow().entries.len(), 2);
+        assert_eq!(fired(&timers, start + 30 * MS), [2, 3]);
+    }
+
+    #[test]
+    fn insert_replaces_the_same_user_data() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 10 * MS, None);
+        timers.insert(1, start + 20 * MS, None);
+
+        assert!(fired(&timers, start + 10 * MS).is_empty());
+        assert_eq!(fired(&timers, start + 20 * MS), [1]);
+    }
+
+    #[test]
+    fn update_moves_the_deadline() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 10 * MS, None);
+
+        assert!(timers.update(1, start + 100 * MS));
+        assert!(timers.next_deadline().unwrap() > start + 10 * MS);
+        assert!(fired(&timers, start + 50 * MS).is_empty());
+
+        assert!(timers.update(1, start + 60 * MS));
+        assert_eq!(fired(&timers, start + 60 * MS), [1]);
+
+        // A fired oneshot timer is gone.
+        assert!(!timers.update(1, start + 80 * MS));
+
+        // Moved into the past, it fires on the next call.
+        timers.insert(2, start + 100 * MS, None);
+        assert!(timers.update(2, start));
+        assert_eq!(fired(&timers, start + 61 * MS), [2]);
+        assert!(timers.wheel.borrow().due.is_empty());
+    }
+
+    #[test]
+    fn periodic_timers() {
+        let (timers, start) = wheel();
+        timers.insert(1, start + 10 * MS, Some(10 * MS));
+
+        assert_eq!(timers.fire(start + 10 * MS), [(1, true)]);
+        assert_eq!(timers.next_deadline(), Some(start + 20 * MS));
+        assert_eq!(timers.fire(start + 20 * MS), [(1, true)]);
+
+        // Missed periods fire once.
+        assert_eq!(timers.fire(start + 55 * MS), [(1, true)]);
+        assert_eq!(timers.next_deadline(), Some(start + 60 * MS));
+
+        assert!(timers.remove(1));
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn cascade_across_levels() {
+        let (timers, start) = wheel();
+        let deadlines = [
+            70 * MS,
+            5_000 * MS,
+            300_000 * MS,
+            Duration::from_secs(20_000),
+            Duration::from_secs(2_000_000)
+        ];
+
+        for (user_data, &dur) in deadlines.iter().enumerate() {
+            timers.insert(user_data as u64, start + dur, None);
+        }
+
+        for (user_data, &dur) in deadlines.iter().enumerate() {
+            let deadline = start + dur;
+
+            // Cascading may arm intermediate deadlines, but never past the timer.
+            while timers.next_deadline().unwrap() < deadline {
+                let now = timers.next_deadline().unwrap();
+                assert!(fired(&timers, now).is_empty());
+            }
+
+            assert_eq!(timers.next_deadline(), Some(deadline));
+            assert!(fired(&timers, deadline - MS).is_empty());
+            assert_eq!(fired(&timers, deadline), [user_data as u64]);
+        }
+    }
+
+    #[test]
+    fn far_future_does_not_fire_early() {
+        let (timers, start) = wheel();
+        let max = Duration::from_millis(MAX_TICKS);
+        let deadline = start + max * 2 + 5 * MS;
+        timers.insert(1, deadline, None);
+
+        // Parked at the edge of the wheel.
+        assert!(timers.next_deadline().unwrap() < start + max);
+        assert!(fired(&timers, start + max).is_empty());
+        assert!(fired(&timers, start + max * 2).is_empty());
+
+        while timers.next_deadline().unwrap() < deadline {
+            let now = timers.next_deadline().unwrap();
+            assert!(fired(&timers, now).is_empty());
+        }
+
+        assert_eq!(fired(&timers, deadline), [1]);
+    }
+}
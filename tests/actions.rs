@@ -0,0 +1,189 @@
+mod common;
+
+use std::io;
+use std::time::{ Duration, Instant };
+use std::os::unix::io::AsRawFd;
+use io_uring::{ types, opcode, squeue };
+use ritsu::{ pipe, Handle, LocalHandle };
+use ritsu::actions::{ action, with_timeout, Chain, PushError };
+use common::run;
+
+
+/// A handle that only implements `push`.
+struct PushOnly(LocalHandle);
+
+impl Handle for PushOnly {
+    unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        self.0.push(entry)
+    }
+}
+
+fn read_e(fd: &impl AsRawFd, buf: &mut [u8]) -> squeue::Entry {
+    opcode::Read::new(types::Fd(fd.as_raw_fd()), buf.as_mut_ptr(), buf.len() as _)
+        .build()
+}
+
+#[test]
+fn with_timeout_expires() {
+    run(|handle| async move {
+        let (reader, _writer) = pipe().unwrap();
+        let mut buf = Box::new([0; 8]);
+        let entry = read_e(&reader, &mut buf[..]);
+
+        let start = Instant::now();
+        let action = unsafe {
+            with_timeout(&handle, (reader, buf), entry, Duration::from_millis(20))
+                .map_err(PushError::into_error)
+                .unwrap()
+        };
+
+        let timed_out = match action.await {
+            Ok(_) => panic!("the read should have timed out"),
+            Err(timed_out) => timed_out
+        };
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let (reader, buf) = timed_out.into_inner();
+        assert!(reader.as_raw_fd() >= 0);
+        assert_eq!(*buf, [0; 8]);
+    });
+}
+
+#[test]
+fn with_timeout_expires_in_io_wq() {
+    run(|handle| async move {
+        // A blocking read forced into io-wq, depending on the kernel
+        // it is interrupted rather than cancelled, either way it timed out.
+        let (reader, _writer) = pipe().unwrap();
+        let mut buf = Box::new([0; 8]);
+        let entry = read_e(&reader, &mut buf[..]).flags(squeue::Flags::ASYNC);
+
+        let action = unsafe {
+            with_timeout(&handle, (reader, buf), entry, Duration::from_millis(20))
+                .map_err(PushError::into_error)
+                .unwrap()
+        };
+
+        let timed_out = action.await.map(drop).unwrap_err();
+        assert_eq!(format!("{:?}", timed_out), "TimedOut { .. }");
+        assert_eq!(timed_out.into_error().kind(), io::ErrorKind::TimedOut);
+    });
+}
+
+#[test]
+fn with_timeout_completes_first() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let mut writer = std::fs::File::from(std::os::unix::io::OwnedFd::from(writer));
+        io::Write::write_all(&mut writer, b"hello").unwrap();
+
+        let mut buf = Box::new([0; 8]);
+        let entry = read_e(&reader, &mut buf[..]);
+
+        let action = unsafe {
+            with_timeout(&handle, (reader, buf), entry, Duration::from_secs(10))
+                .map_err(PushError::into_error)
+                .unwrap()
+        };
+
+        let start = Instant::now();
+        let ((_, buf), cqe) = action.await.ok().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(cqe.result(), 5);
+        assert_eq!(&buf[..5], b"hello");
+    });
+}
+
+#[test]
+fn push_multiple_default() {
+    run(|handle| async move {
+        let handle = PushOnly(handle);
+        let nop = opcode::Nop::new().build().user_data(1);
+
+        unsafe {
+            handle.push_multiple(&[]).unwrap();
+            handle.push_multiple(std::slice::from_ref(&nop)).unwrap();
+
+            let err = handle.push_multiple(&[nop.clone(), nop]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        }
+
+        // A linked timeout needs two entries pushed together.
+        let (reader, _writer) = pipe().unwrap();
+        let mut buf = Box::new([0; 8]);
+        let entry = read_e(&reader, &mut buf[..]);
+        let ret = unsafe {
+            with_timeout(&handle, (reader, buf), entry, Duration::from_millis(1))
+        };
+        let err = ret.map(drop).map_err(PushError::into_error).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    });
+}
+
+#[test]
+fn chain_failure_cancels_the_rest() {
+    run(|handle| async move {
+        let bad = opcode::Read::new(types::Fd(-1), std::ptr::null_mut(), 0).build();
+        let nop = opcode::Nop::new().build();
+
+        let chain = Chain::new()
+            .link(nop.clone())
+            .link(bad.clone())
+            .link(nop.clone())
+            .link(nop.clone());
+        assert_eq!(chain.len(), 4);
+
+        let ((), cqes) = unsafe {
+            chain.submit(&handle, ())
+                .map_err(PushError::into_error)
+                .unwrap()
+                .await
+        };
+        let results = cqes.iter().map(|cqe| cqe.result()).collect::<Vec<_>>();
+        assert_eq!(results, [0, -libc::EBADF, -libc::ECANCELED, -libc::ECANCELED]);
+
+        // A hard link keeps going after the failure.
+        let chain = Chain::new()
+            .link(bad)
+            .hard_link(nop.clone())
+            .link(nop);
+
+        let ((), cqes) = unsafe {
+            chain.submit(&handle, ())
+                .map_err(PushError::into_error)
+                .unwrap()
+                .await
+        };
+        let results = cqes.iter().map(|cqe| cqe.result()).collect::<Vec<_>>();
+        assert_eq!(results, [-libc::EBADF, 0, 0]);
+    });
+}
+
+#[test]
+fn batch_pushes_and_submits() {
+    run(|handle| async move {
+        // More than the submission queue holds, so the batch submits on the way.
+        let count = 1000;
+        let actions = {
+            let batch = handle.batch();
+            (0..count)
+                .map(|_| unsafe {
+                    action(&batch, (), opcode::Nop::new().build())
+                        .map_err(PushError::into_error)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for action in actions {
+            let ((), cqe) = action.await;
+            assert_eq!(cqe.result(), 0);
+        }
+
+        // Tokens only, nothing to wait for.
+        let nops = vec![opcode::Nop::new().build().user_data(1); count];
+        unsafe {
+            handle.push_batch(&nops).unwrap();
+        }
+    });
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use std::io::Write;
+use std::fs::File;
+use std::os::unix::io::OwnedFd;
+use bytes::{ Bytes, BytesMut };
+use ritsu::{ pipe, Proactor };
+use ritsu::buf::{ IoBuf, IoBufMut, FixedIoBuf };
+use ritsu::actions::io::{ read_buf, write_buf, read_fixed, write_fixed };
+use common::run;
+
+
+#[test]
+fn growable_buffers() {
+    let mut vec = Vec::with_capacity(16);
+    vec.extend_from_slice(b"abc");
+    assert_eq!(vec.bytes_init(), 3);
+    assert_eq!(vec.bytes_total(), vec.capacity());
+    assert_eq!(vec.stable_ptr(), vec.as_ptr());
+
+    unsafe {
+        vec.stable_mut_ptr().add(3).write_bytes(b'd', 2);
+        vec.set_init(5);
+        // Never shrinks.
+        vec.set_init(1);
+    }
+    assert_eq!(vec, b"abcdd");
+
+    let mut bytes = BytesMut::with_capacity(16);
+    bytes.extend_from_slice(b"abc");
+    assert_eq!(bytes.bytes_init(), 3);
+    assert_eq!(bytes.bytes_total(), bytes.capacity());
+
+    unsafe {
+        bytes.stable_mut_ptr().add(3).write(b'd');
+        bytes.set_init(4);
+        bytes.set_init(0);
+    }
+    assert_eq!(&bytes[..], b"abcd");
+}
+
+#[test]
+fn fixed_size_buffers() {
+    let mut boxed = vec![1u8; 8].into_boxed_slice();
+    assert_eq!(boxed.bytes_init(), 8);
+    assert_eq!(boxed.bytes_total(), 8);
+    unsafe {
+        boxed.set_init(0);
+    }
+    assert_eq!(boxed.bytes_init(), 8);
+
+    let frozen = Bytes::from_static(b"frozen");
+    assert_eq!((frozen.bytes_init(), frozen.bytes_total()), (6, 6));
+
+    let slice: &'static [u8] = b"slice";
+    assert_eq!((slice.bytes_init(), slice.bytes_total()), (5, 5));
+    assert_eq!(slice.stable_ptr(), slice.as_ptr());
+
+    let text = "text";
+    assert_eq!((text.bytes_init(), text.bytes_total()), (4, 4));
+}
+
+#[test]
+fn slices() {
+    let mut vec = Vec::with_capacity(16);
+    vec.extend_from_slice(b"0123456789");
+
+    let slice = vec.slice(2..6);
+    assert_eq!((slice.begin(), slice.end()), (2, 6));
+    assert_eq!(slice.bytes_init(), 4);
+    assert_eq!(slice.bytes_total(), 4);
+    assert_eq!(slice.stable_ptr(), unsafe { slice.get_ref().as_ptr().add(2) });
+
+    // Partly initialized.
+    let slice = slice.into_inner().slice(8..);
+    assert_eq!(slice.bytes_init(), 2);
+    assert_eq!(slice.bytes_total(), 16 - 8);
+
+    // Not initialized at all.
+    let vec = slice.into_inner();
+    let slice = vec.slice(12..14);
+    assert_eq!(slice.bytes_init(), 0);
+    assert_eq!(slice.bytes_total(), 2);
+
+    let slice = slice.into_inner().slice(..=3);
+    assert_eq!((slice.begin(), slice.end()), (0, 4));
+}
+
+#[test]
+fn slice_set_init() {
+    let mut vec = Vec::with_capacity(16);
+    vec.extend_from_slice(b"0123");
+
+    // Right after the initialized part, the bytes are appended.
+    let mut slice = vec.slice(4..);
+    unsafe {
+        slice.stable_mut_ptr().write_bytes(b'x', 3);
+        slice.set_init(3);
+    }
+    assert_eq!(slice.bytes_init(), 3);
+    assert_eq!(slice.into_inner(), b"0123xxx");
+
+    // A gap before the slice would be uninitialized, so nothing is marked.
+    let mut vec = b"0123".to_vec();
+    vec.reserve(16);
+    let mut slice = vec.slice(8..);
+    unsafe {
+        slice.stable_mut_ptr().write_bytes(b'x', 2);
+        slice.set_init(2);
+    }
+    assert_eq!(slice.bytes_init(), 0);
+    assert_eq!(slice.into_inner(), b"0123");
+
+    // Inside the initialized part it never shrinks.
+    let mut slice = b"0123456".to_vec().slice(1..3);
+    unsafe {
+        slice.set_init(1);
+    }
+    assert_eq!(slice.bytes_init(), 2);
+    assert_eq!(slice.into_inner(), b"0123456");
+}
+
+#[test]
+#[should_panic(expected = "range end out of bounds")]
+fn slice_out_of_bounds() {
+    let _ = vec![0u8; 4].into_boxed_slice().slice(2..5);
+}
+
+#[test]
+fn read_into_any_buffer() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let mut writer = File::from(OwnedFd::from(writer));
+        let mut reader = Some(reader);
+
+        // A box is read from its start.
+        writer.write_all(b"boxed").unwrap();
+        let (fd, buf, n) = read_buf(&handle, &mut reader, vec![0; 8].into_boxed_slice(), None)
+            .await
+            .unwrap();
+        reader = Some(fd);
+        assert_eq!(n, 5);
+        assert_eq!(&buf[..n], b"boxed");
+
+        // So is a slice of it.
+        writer.write_all(b"mid").unwrap();
+        let (fd, buf, n) = read_buf(&handle, &mut reader, buf.slice(5..), None).await.unwrap();
+        reader = Some(fd);
+        assert_eq!(n, 3);
+        assert_eq!(&buf.into_inner()[..], b"boxedmid");
+
+        // A slice after the initialized part appends.
+        writer.write_all(b"tail").unwrap();
+        let mut vec = Vec::with_capacity(16);
+        vec.extend_from_slice(b"head");
+        let (fd, buf, n) = read_buf(&handle, &mut reader, vec.slice(4..), None).await.unwrap();
+        reader = Some(fd);
+        assert_eq!(n, 4);
+        assert_eq!(buf.into_inner(), b"headtail");
+
+        // A vec is read from its start too.
+        writer.write_all(b"new").unwrap();
+        let (fd, buf, n) = read_buf(&handle, &mut reader, b"old data".to_vec(), None).await.unwrap();
+        reader = Some(fd);
+        assert_eq!(n, 3);
+        assert_eq!(buf, b"new data");
+
+        // A zero length buffer reads nothing, it is not the end of file.
+        let (_, _, n) = read_buf(&handle, &mut reader, Vec::new(), None).await.unwrap();
+        assert_eq!(n, 0);
+
+        // And written back from a box.
+        let (reader2, writer2) = pipe().unwrap();
+        let mut writer2 = Some(writer2);
+        let (_, _, n) = write_buf(&handle, &mut writer2, buf.into_boxed_slice(), None).await.unwrap();
+        assert_eq!(n, 8);
+
+        let (_, buf, n) = read_buf(&handle, &mut Some(reader2), vec![0; 16].into_boxed_slice(), None)
+            .await
+            .unwrap();
+        assert_eq!(&buf[..n], b"new data");
+    });
+}
+
+#[test]
+fn fixed_buffers() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let mut bufs = proactor.register_buffers(2, 64).unwrap();
+    let second = bufs.pop().unwrap();
+    let mut first = bufs.pop().unwrap();
+
+    assert_eq!((first.buf_index(), second.buf_index()), (0, 1));
+    assert_eq!((first.bytes_init(), first.bytes_total()), (0, 64));
+    assert!(first.as_slice().is_empty());
+
+    unsafe {
+        first.stable_mut_ptr().copy_from(b"fixed".as_ptr(), 5);
+        first.set_init(5);
+    }
+    assert_eq!(first.as_slice(), b"fixed");
+
+    let first = first.slice(..5);
+    assert_eq!(first.buf_index(), 0);
+
+    let (reader, writer) = pipe().unwrap();
+
+    let second = ritsu::block_on(&mut proactor, async move {
+        let mut writer = Some(writer);
+        let (writer, _, n) = write_fixed(&handle, &mut writer, first, None).await.unwrap();
+        assert_eq!(n, 5);
+
+        // Bytes before the slice are not initialized, so nothing is marked.
+        let mut reader = Some(reader);
+        let (fd, second, n) = read_fixed(&handle, &mut reader, second.slice(3..), None).await.unwrap();
+        reader = Some(fd);
+        assert_eq!(n, 5);
+        let second = second.into_inner();
+        assert!(second.as_slice().is_empty());
+
+        // Read from the start.
+        File::from(OwnedFd::from(writer)).write_all(b"again").unwrap();
+        let (_, second, n) = read_fixed(&handle, &mut reader, second, None).await.unwrap();
+        assert_eq!(n, 5);
+        second
+    }).unwrap();
+
+    assert_eq!(second.as_slice(), b"again");
+
+    let mut second = second;
+    second.clear();
+    assert_eq!(second.bytes_init(), 0);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::os::unix::io::AsRawFd;
+use ritsu::pipe;
+use ritsu::fs::Dir;
+use ritsu::process::Command;
+use ritsu::actions::fs::CloseOnDrop;
+use ritsu::actions::io::{ read_buf, SharedFd };
+use common::{ run, TempDir };
+
+
+fn is_open(fd: i32) -> bool {
+    unsafe { libc::fcntl(fd, libc::F_GETFD) != -1 }
+}
+
+#[test]
+fn close_on_drop_closes_through_the_ring() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let writer = CloseOnDrop::new(handle.clone(), writer);
+        let fd = writer.as_raw_fd();
+
+        drop(writer);
+
+        // The close is submitted on drop, so the reader sees the end of the pipe.
+        let (_, buf, _) = read_buf(&handle, &mut Some(reader), Vec::with_capacity(8), None)
+            .await
+            .unwrap();
+        assert!(buf.is_empty());
+        assert!(!is_open(fd));
+    });
+}
+
+#[test]
+fn shared_close_on_drop_closes_after_last_clone() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let writer = SharedFd::new(CloseOnDrop::new(handle.clone(), writer));
+        let writer2 = writer.clone();
+
+        drop(writer);
+        assert!(is_open(writer2.as_raw_fd()));
+        drop(writer2);
+
+        let (_, buf, _) = read_buf(&handle, &mut Some(reader), Vec::with_capacity(8), None)
+            .await
+            .unwrap();
+        assert!(buf.is_empty());
+    });
+}
+
+#[test]
+fn close_on_drop_into_inner_keeps_the_fd() {
+    run(|handle| async move {
+        let (_reader, writer) = pipe().unwrap();
+        let fd = writer.as_raw_fd();
+
+        let writer = CloseOnDrop::into_inner(CloseOnDrop::new(&handle, writer));
+        assert!(is_open(fd));
+
+        CloseOnDrop::new(&handle, writer).close().await.unwrap();
+        assert!(!is_open(fd));
+    });
+}
+
+#[test]
+fn child_pipes_close_on_drop() {
+    run(|handle| async move {
+        let child = Command::new("cat")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn(&handle)
+            .unwrap();
+        let stdin = child.stdin.as_ref().unwrap().as_raw_fd();
+        let stdout = child.stdout.as_ref().unwrap().as_raw_fd();
+
+        drop(child);
+
+        // Wait on a read of an empty pipe's closed end to let the closes complete.
+        let (reader, writer) = pipe().unwrap();
+        drop(writer);
+        read_buf(&handle, &mut Some(reader), Vec::with_capacity(1), None).await.unwrap();
+
+        assert!(!is_open(stdin));
+        assert!(!is_open(stdout));
+    });
+}
+
+#[test]
+fn dir_beneath_closes_parents() {
+    let tmp = TempDir::new("close-parents");
+    let root = tmp.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    fs::write(root.join("sub/file"), b"").unwrap();
+
+    run(|handle| async move {
+        let mut dir = Dir::open_ambient(&handle, root).await.unwrap();
+        dir.set_beneath(true);
+
+        let before = fs::read_dir("/proc/self/fd").unwrap().count();
+
+        for _ in 0..16 {
+            dir.statx(Path::new("sub/file")).await.unwrap();
+            assert!(dir.statx(Path::new("sub/missing")).await.is_err());
+        }
+
+        // Give the detached closes a round trip to complete.
+        dir.statx(Path::new("sub")).await.unwrap();
+
+        let after = fs::read_dir("/proc/self/fd").unwrap().count();
+        assert!(after <= before + 1, "{} parents left open", after - before);
+    });
+}
@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+use std::{ fs, process };
+use std::path::{ Path, PathBuf };
+use std::pin::Pin;
+use std::future::{ poll_fn, Future };
+use std::task::Poll;
+use futures_core::Stream;
+use ritsu::{ Proactor, LocalHandle };
+
+
+/// A fresh directory that is removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir()
+            .join(format!("ritsu-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run a future on a new proactor.
+pub fn run<F, Fut>(f: F) -> Fut::Output
+where
+    F: FnOnce(LocalHandle) -> Fut,
+    Fut: Future
+{
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    ritsu::block_on(&mut proactor, f(handle)).unwrap()
+}
+
+/// The next item of a stream.
+pub async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+/// Run both futures to completion.
+pub async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let mut a = Box::pin(a);
+    let mut b = Box::pin(b);
+    let (mut ra, mut rb) = (None, None);
+
+    poll_fn(|cx| {
+        if ra.is_none() {
+            if let Poll::Ready(val) = a.as_mut().poll(cx) {
+                ra = Some(val);
+            }
+        }
+
+        if rb.is_none() {
+            if let Poll::Ready(val) = b.as_mut().poll(cx) {
+                rb = Some(val);
+            }
+        }
+
+        match (ra.is_some(), rb.is_some()) {
+            (true, true) => Poll::Ready((ra.take().unwrap(), rb.take().unwrap())),
+            _ => Poll::Pending
+        }
+    }).await
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use ritsu::fs::Dir;
+use ritsu::actions::fs::{ OpenOptions, RenameFlags };
+use common::{ run, TempDir };
+
+
+#[test]
+fn dir_ops_are_relative() {
+    let tmp = TempDir::new("dir-relative");
+    let root = tmp.path();
+
+    run(|handle| async move {
+        let dir = Dir::open_ambient(&handle, root).await.unwrap();
+
+        dir.create_dir(Path::new("sub")).await.unwrap();
+        assert!(root.join("sub").is_dir());
+
+        let file = dir.open(
+            OpenOptions::new().write(true).create(true),
+            Path::new("sub/a")
+        ).await.unwrap();
+        drop(file);
+
+        let stat = dir.statx(Path::new("sub/a")).await.unwrap();
+        assert_eq!(stat.stx_size, 0);
+
+        dir.rename(Path::new("sub/a"), Path::new("b"), RenameFlags::empty()).await.unwrap();
+        assert!(root.join("b").is_file());
+
+        dir.remove_file(Path::new("b")).await.unwrap();
+        assert!(!root.join("b").exists());
+
+        dir.close().await.unwrap();
+    });
+}
+
+#[test]
+fn dir_beneath_rejects_escapes() {
+    let tmp = TempDir::new("dir-beneath");
+    let root = tmp.join("root");
+    let root = root.as_path();
+    fs::create_dir(root).unwrap();
+    fs::create_dir(root.join("sub")).unwrap();
+    fs::write(root.join("sub/inside"), b"inside").unwrap();
+    fs::write(tmp.join("outside"), b"outside").unwrap();
+    std::os::unix::fs::symlink("../outside", root.join("escape")).unwrap();
+    std::os::unix::fs::symlink("sub/inside", root.join("inner")).unwrap();
+    std::os::unix::fs::symlink("..", root.join("sub/up")).unwrap();
+
+    run(|handle| async move {
+        let mut dir = Dir::open_ambient(&handle, root).await.unwrap();
+        let read = OpenOptions::new().read(true).clone();
+
+        // Unrestricted, `..` and symlinks resolve freely.
+        assert!(dir.open(&read, Path::new("../outside")).await.is_ok());
+        assert!(dir.open(&read, Path::new("escape")).await.is_ok());
+
+        dir.set_beneath(true);
+
+        let exdev = Some(libc::EXDEV);
+        for path in ["../outside", "escape", "/etc/hostname", "sub/up/../outside"] {
+            let err = dir.open(&read, Path::new(path)).await.unwrap_err();
+            assert_eq!(err.raw_os_error(), exdev, "{}", path);
+        }
+
+        let mut file = dir.open(&read, Path::new("inner")).await.unwrap();
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "inside");
+
+        // `..` that stays beneath is fine.
+        assert!(dir.open(&read, Path::new("sub/up/sub/inside")).await.is_ok());
+
+        let err = dir.statx(Path::new("../outside")).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), exdev);
+        let err = dir.remove_file(Path::new("sub/up/../outside")).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), exdev);
+        let err = dir.create_dir(Path::new("/tmp/x")).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), exdev);
+        let err = dir.rename(Path::new("sub/inside"), Path::new("../moved"), RenameFlags::empty())
+            .await
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), exdev);
+
+        // The last component is not followed, so a symlink can be stat'ed and removed.
+        let stat = dir.statx(Path::new("escape")).await.unwrap();
+        assert_eq!(stat.stx_mode as u32 & libc::S_IFMT, libc::S_IFLNK);
+        dir.remove_file(Path::new("escape")).await.unwrap();
+
+        assert!(tmp.join("outside").exists());
+        assert!(root.join("sub/inside").exists());
+    });
+}
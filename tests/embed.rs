@@ -0,0 +1,153 @@
+use std::io;
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{ Context, Poll, Waker };
+use std::time::{ Duration, Instant };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use ritsu::Proactor;
+use ritsu::time::sleep;
+use ritsu::actions::nop;
+
+
+/// A minimal epoll loop, as an application would already have.
+struct Epoll(RawFd);
+
+impl Epoll {
+    fn new() -> Epoll {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        assert!(fd >= 0);
+        Epoll(fd)
+    }
+
+    fn add(&self, fd: RawFd) {
+        let mut event = libc::epoll_event { events: libc::EPOLLIN as _, u64: fd as _ };
+        let ret = unsafe { libc::epoll_ctl(self.0, libc::EPOLL_CTL_ADD, fd, &mut event) };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    }
+
+    /// Returns the ready fds.
+    ///
+    /// The ring interrupts the wait to run its task work, which is retried.
+    fn wait(&self, timeout: Duration) -> Vec<RawFd> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+
+        loop {
+            let n = unsafe {
+                libc::epoll_wait(self.0, events.as_mut_ptr(), events.len() as _, timeout.as_millis() as _)
+            };
+
+            if n >= 0 {
+                return events[..n as usize].iter().map(|event| event.u64 as RawFd).collect();
+            }
+
+            let err = io::Error::last_os_error();
+            assert_eq!(err.kind(), io::ErrorKind::Interrupted, "{}", err);
+        }
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Drive `f` from the epoll loop until it completes.
+fn drive<F: Future>(proactor: &mut Proactor, epoll: &Epoll, f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    let mut cx = Context::from_waker(Waker::noop());
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    loop {
+        if let Poll::Ready(ret) = f.as_mut().poll(&mut cx) {
+            return ret;
+        }
+
+        // Submit what the task pushed.
+        proactor.poll_completions().unwrap();
+
+        assert!(Instant::now() < deadline, "the task never completed");
+        epoll.wait(Duration::from_millis(100));
+
+        proactor.poll_completions().unwrap();
+    }
+}
+
+#[test]
+fn poll_completions_without_parking() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+
+    // Nothing pushed, nothing to dispatch.
+    assert_eq!(proactor.poll_completions().unwrap(), 0);
+
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut action = Box::pin(nop(&handle));
+    assert!(action.as_mut().poll(&mut cx).is_pending());
+
+    // Completions are dispatched before the submission,
+    // so the nop is only dispatched by the next call.
+    assert_eq!(proactor.poll_completions().unwrap(), 0);
+    assert_eq!(proactor.poll_completions().unwrap(), 1);
+
+    match action.as_mut().poll(&mut cx) {
+        Poll::Ready(ret) => ret.unwrap(),
+        Poll::Pending => panic!("the nop was dispatched")
+    }
+    assert_eq!(proactor.metrics().parks(), 0);
+}
+
+#[test]
+fn ring_fd_in_epoll() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let epoll = Epoll::new();
+    epoll.add(proactor.as_raw_fd());
+
+    // An idle ring is not readable.
+    proactor.poll_completions().unwrap();
+    assert!(epoll.wait(Duration::from_millis(10)).is_empty());
+
+    let start = Instant::now();
+    drive(&mut proactor, &epoll, sleep(&handle, Duration::from_millis(20))).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // The timer wheel is driven by poll_completions too.
+    proactor.enable_timer_wheel();
+    let start = Instant::now();
+    drive(&mut proactor, &epoll, sleep(&handle, Duration::from_millis(20))).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    assert_eq!(proactor.metrics().parks(), 0);
+}
+
+#[test]
+fn registered_eventfd_in_epoll() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let eventfd = proactor.register_eventfd().unwrap();
+    let epoll = Epoll::new();
+    epoll.add(eventfd.as_raw_fd());
+
+    // Only one eventfd per ring.
+    assert!(proactor.register_eventfd().is_err());
+
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut timer = sleep(&handle, Duration::from_millis(10));
+    assert!(Pin::new(&mut timer).poll(&mut cx).is_pending());
+    proactor.poll_completions().unwrap();
+
+    assert_eq!(epoll.wait(Duration::from_secs(5)), [eventfd.as_raw_fd()]);
+
+    let mut buf = [0; 8];
+    let n = unsafe { libc::read(eventfd.as_raw_fd(), buf.as_mut_ptr().cast(), 8) };
+    assert_eq!(n, 8);
+
+    assert_eq!(proactor.poll_completions().unwrap(), 1);
+    match Pin::new(&mut timer).poll(&mut cx) {
+        Poll::Ready(ret) => ret.unwrap(),
+        Poll::Pending => panic!("the sleep was dispatched")
+    }
+}
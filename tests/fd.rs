@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs::File;
+use std::net::{ TcpListener, TcpStream, UdpSocket };
+use std::process::{ Command, Stdio };
+use std::os::unix::io::{ AsRawFd, OwnedFd };
+use std::os::unix::net::{ UnixStream, UnixDatagram };
+use ritsu::{ pipe, Handle };
+use ritsu::actions::io::{ read_buf, write_buf, TrustedAsRawFd, UnsafeFd };
+use common::{ run, TempDir };
+
+
+/// Write `data` to `writer` and read it back from `reader`, returns both.
+async fn roundtrip<H, W, R>(handle: H, writer: W, reader: R, data: &'static [u8]) -> (W, R)
+where
+    H: Handle,
+    W: TrustedAsRawFd,
+    R: TrustedAsRawFd
+{
+    let mut writer = Some(writer);
+    let (writer, _, n) = write_buf(&handle, &mut writer, data, None).await.unwrap();
+    assert_eq!(n, data.len());
+
+    let mut reader = Some(reader);
+    let (reader, buf, n) = read_buf(&handle, &mut reader, Vec::with_capacity(64), None).await.unwrap();
+    assert_eq!(n, data.len());
+    assert_eq!(buf, data);
+
+    (writer, reader)
+}
+
+#[test]
+fn socket_types() {
+    run(|handle| async move {
+        let (a, b) = UnixStream::pair().unwrap();
+        let (b, a) = roundtrip(&handle, b, a, b"unix stream").await;
+        roundtrip(&handle, a, b, b"and back").await;
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        roundtrip(&handle, a, b, b"unix datagram").await;
+
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        a.connect(b.local_addr().unwrap()).unwrap();
+        b.connect(a.local_addr().unwrap()).unwrap();
+        roundtrip(&handle, a, b, b"udp").await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let (client, server) = roundtrip(&handle, client, server, b"tcp").await;
+        roundtrip(&handle, server, client, b"tcp reply").await;
+
+        // A listener is accepted on with actions too, so it must be trusted.
+        fn trusted<T: TrustedAsRawFd>(_: &T) {}
+        trusted(&listener);
+    });
+}
+
+#[test]
+fn owned_and_child_fds() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        roundtrip(&handle, OwnedFd::from(writer), OwnedFd::from(reader), b"owned fd").await;
+
+        let (reader, writer) = pipe().unwrap();
+        roundtrip(&handle, Box::new(writer), Box::new(reader), b"boxed").await;
+
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (stdin, stdout) = roundtrip(&handle, stdin, stdout, b"child stdio").await;
+        drop(stdin);
+
+        let mut stdout = Some(stdout);
+        let (_, buf, n) = read_buf(&handle, &mut stdout, Vec::with_capacity(8), None).await.unwrap();
+        assert_eq!(n, 0);
+        assert!(buf.is_empty());
+        assert!(child.wait().unwrap().success());
+    });
+}
+
+#[test]
+fn file_and_unsafe_fd() {
+    let dir = TempDir::new("fd");
+
+    run(|handle| async move {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(dir.join("file"))
+            .unwrap();
+
+        let mut fd = Some(file);
+        let (file, _, n) = write_buf(&handle, &mut fd, &b"0123456789"[..], Some(0)).await.unwrap();
+        assert_eq!(n, 10);
+
+        let mut fd = Some(file);
+        let (file, buf, _) = read_buf(&handle, &mut fd, Vec::with_capacity(4), Some(6)).await.unwrap();
+        assert_eq!(buf, b"6789");
+
+        // The file outlives every action given the raw fd.
+        let raw = unsafe { UnsafeFd::new(file.as_raw_fd()) };
+        let mut fd = Some(raw);
+        let (_, buf, _) = read_buf(&handle, &mut fd, Vec::with_capacity(3), Some(2)).await.unwrap();
+        assert_eq!(buf, b"234");
+        drop(file);
+
+        // A closed fd is reported, and the value is given back.
+        let mut fd = Some(unsafe { UnsafeFd::new(-1) });
+        assert!(read_buf(&handle, &mut fd, Vec::with_capacity(1), None).await.is_err());
+        assert!(fd.is_some());
+    });
+}
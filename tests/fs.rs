@@ -0,0 +1,181 @@
+mod common;
+
+use std::io;
+use std::path::Path;
+use std::fs::{ self, File, OpenOptions };
+use std::os::unix::fs::{ MetadataExt, PermissionsExt };
+use ritsu::actions::fs::{
+    allocate, set_len, AllocateFlags,
+    create_dir, remove_dir, remove_file, rename, RenameFlags,
+    hard_link, symlink, statx
+};
+use common::{ run, TempDir };
+
+
+#[test]
+fn set_len_truncates_and_extends() {
+    let dir = TempDir::new("set-len");
+    let path = dir.join("file");
+    let path = path.as_path();
+    fs::write(path, b"hello world").unwrap();
+
+    run(|handle| async move {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+
+        let file = set_len(&handle, &mut Some(file), 5).await.unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"hello");
+
+        let _file = set_len(&handle, &mut Some(file), 4096).await.unwrap();
+        let data = fs::read(path).unwrap();
+        assert_eq!(data.len(), 4096);
+        assert!(data[5..].iter().all(|&b| b == 0));
+    });
+}
+
+#[test]
+fn set_len_errors_give_the_fd_back() {
+    let dir = TempDir::new("set-len-err");
+    let path = dir.join("file");
+    let path = path.as_path();
+    fs::write(path, b"hello").unwrap();
+
+    run(|handle| async move {
+        // Not opened for writing.
+        let mut fd = Some(File::open(path).unwrap());
+        let err = set_len(&handle, &mut fd, 1).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert!(fd.is_some());
+
+        // Does not fit in `off_t`.
+        let mut fd = Some(OpenOptions::new().write(true).open(path).unwrap());
+        let err = set_len(&handle, &mut fd, u64::MAX).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert!(fd.is_some());
+    });
+
+    assert_eq!(fs::read(path).unwrap(), b"hello");
+}
+
+#[test]
+fn allocate_extends_and_punches() {
+    let dir = TempDir::new("allocate");
+    let path = dir.join("file");
+    let path = path.as_path();
+    fs::write(path, vec![1; 8192]).unwrap();
+
+    run(|handle| async move {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+
+        // Keeping the size only reserves blocks.
+        let file = allocate(&handle, &mut Some(file), AllocateFlags::KEEP_SIZE, 8192, 8192)
+            .await.unwrap();
+        assert_eq!(fs::metadata(path).unwrap().len(), 8192);
+
+        let file = allocate(&handle, &mut Some(file), AllocateFlags::empty(), 0, 16384)
+            .await.unwrap();
+        assert_eq!(fs::metadata(path).unwrap().len(), 16384);
+
+        let flags = AllocateFlags::PUNCH_HOLE | AllocateFlags::KEEP_SIZE;
+        match allocate(&handle, &mut Some(file), flags, 0, 4096).await {
+            Ok(_) => {
+                let data = fs::read(path).unwrap();
+                assert!(data[..4096].iter().all(|&b| b == 0));
+                assert!(data[4096..8192].iter().all(|&b| b == 1));
+            },
+
+            // Not every filesystem can punch holes.
+            Err(err) => assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP))
+        }
+    });
+
+    assert!(fs::metadata(path).unwrap().blocks() > 0);
+}
+
+#[test]
+fn namespace_ops() {
+    let dir = TempDir::new("namespace");
+    let dir = dir.path();
+
+    run(|handle| async move {
+        let sub = dir.join("sub");
+        create_dir(&handle, &sub).await.unwrap();
+        assert!(sub.is_dir());
+
+        let err = create_dir(&handle, &sub).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        let a = sub.join("a");
+        let b = sub.join("b");
+        fs::write(&a, b"a").unwrap();
+        fs::write(&b, b"b").unwrap();
+
+        let err = rename(&handle, &a, &b, RenameFlags::NOREPLACE).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        rename(&handle, &a, &b, RenameFlags::EXCHANGE).await.unwrap();
+        assert_eq!(fs::read(&a).unwrap(), b"b");
+        assert_eq!(fs::read(&b).unwrap(), b"a");
+
+        rename(&handle, &a, &b, RenameFlags::empty()).await.unwrap();
+        assert!(!a.exists());
+        assert_eq!(fs::read(&b).unwrap(), b"b");
+
+        let link = sub.join("link");
+        hard_link(&handle, &b, &link).await.unwrap();
+        assert_eq!(fs::metadata(&b).unwrap().nlink(), 2);
+
+        let sym = sub.join("sym");
+        symlink(&handle, Path::new("b"), &sym).await.unwrap();
+        assert_eq!(fs::read_link(&sym).unwrap(), Path::new("b"));
+        assert_eq!(fs::read(&sym).unwrap(), b"b");
+
+        let stat = statx(&handle, &link).await.unwrap();
+        assert_eq!(stat.stx_size, 1);
+        assert_eq!(stat.stx_nlink, 2);
+
+        let err = remove_dir(&handle, &sub).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOTEMPTY));
+
+        for name in ["b", "link", "sym"] {
+            remove_file(&handle, &sub.join(name)).await.unwrap();
+        }
+
+        let err = remove_file(&handle, &b).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        remove_dir(&handle, &sub).await.unwrap();
+        assert!(!sub.exists());
+    });
+}
+
+#[test]
+fn copy_contents_and_permissions() {
+    let dir = TempDir::new("copy");
+    let from = dir.join("from");
+    let to = dir.join("to");
+    let empty = dir.join("empty");
+    let (from, to, empty) = (from.as_path(), to.as_path(), empty.as_path());
+
+    // Several pipe chunks, and not a multiple of the page size.
+    let data: Vec<u8> = (0..(3 << 20) + 123).map(|i| (i % 251) as u8).collect();
+    fs::write(from, &data).unwrap();
+    // Bits that a usual umask would strip.
+    fs::set_permissions(from, fs::Permissions::from_mode(0o727)).unwrap();
+    // A longer destination is truncated.
+    fs::write(to, vec![1; 4 << 20]).unwrap();
+    fs::write(empty, b"").unwrap();
+
+    run(|handle| async move {
+        let n = ritsu::fs::copy(&handle, from, to).await.unwrap();
+        assert_eq!(n, data.len() as u64);
+        assert!(fs::read(to).unwrap() == data);
+        assert_eq!(fs::metadata(to).unwrap().permissions().mode() & 0o7777, 0o727);
+
+        let n = ritsu::fs::copy(&handle, empty, to).await.unwrap();
+        assert_eq!(n, 0);
+        assert_eq!(fs::metadata(to).unwrap().len(), 0);
+
+        let err = ritsu::fs::copy(&handle, &dir.join("missing"), to).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    });
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{ fs, io, thread };
+use std::fs::File;
+use std::io::Read;
+use std::ops::Bound;
+use std::os::unix::net::UnixStream;
+use ritsu::actions::io::send_file;
+use common::{ run, TempDir };
+
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn send_file_whole_and_ranges() {
+    let dir = TempDir::new("send-file");
+    let path = dir.join("file");
+    let path = path.as_path();
+    // Larger than the pipe, so it takes several splice pairs.
+    let data = pattern(3 << 20);
+    fs::write(path, &data).unwrap();
+
+    let (tx, mut rx) = UnixStream::pair().unwrap();
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        rx.read_to_end(&mut buf).unwrap();
+        buf
+    });
+
+    let n = run(|handle| async move {
+        let mut file = Some(File::open(path).unwrap());
+        let mut socket = Some(tx);
+        let mut total = 0;
+
+        for range in [(0, None), (100, Some(5000)), (data.len() as u64 - 10, None)] {
+            let (file2, socket2, n) = match range {
+                (start, Some(end)) => send_file(&handle, &mut file, &mut socket, start..end).await,
+                (start, None) => send_file(&handle, &mut file, &mut socket, start..).await
+            }.unwrap();
+            file = Some(file2);
+            socket = Some(socket2);
+            total += n;
+        }
+
+        // Past the end of file there is nothing to send.
+        let (_, _, n) = send_file(&handle, &mut file, &mut socket, (data.len() as u64 + 1)..)
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+
+        total
+    });
+
+    let received = reader.join().unwrap();
+    let data = pattern(3 << 20);
+    let mut expected = data.clone();
+    expected.extend_from_slice(&data[100..5000]);
+    expected.extend_from_slice(&data[data.len() - 10..]);
+
+    assert_eq!(n, expected.len() as u64);
+    assert!(received == expected);
+}
+
+#[test]
+fn send_file_out_of_range() {
+    let dir = TempDir::new("send-file-range");
+    let path = dir.join("file");
+    fs::write(&path, b"data").unwrap();
+
+    run(|handle| async move {
+        let mut file = Some(File::open(&path).unwrap());
+        let (tx, _rx) = UnixStream::pair().unwrap();
+        let mut socket = Some(tx);
+
+        let ranges: [(Bound<u64>, Bound<u64>); 3] = [
+            (Bound::Excluded(u64::MAX), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Included(u64::MAX)),
+            (Bound::Included(i64::MAX as u64 + 1), Bound::Unbounded)
+        ];
+
+        for range in ranges {
+            let err = send_file(&handle, &mut file, &mut socket, range).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(file.is_some() && socket.is_some());
+        }
+
+        // Both are given back and still usable.
+        let (_, _, n) = send_file(&handle, &mut file, &mut socket, 1..3).await.unwrap();
+        assert_eq!(n, 2);
+    });
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::io::{ self, Write };
+use std::fs::File;
+use std::net::{ TcpListener, TcpStream };
+use std::time::Duration;
+use std::os::unix::io::{ AsRawFd, OwnedFd };
+use ritsu::pipe;
+use ritsu::time::sleep;
+use ritsu::actions::io::{ lines, Lines };
+use common::{ run, join };
+
+
+#[test]
+fn lines_across_reads() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let mut writer = File::from(OwnedFd::from(writer));
+
+        let write = {
+            let handle = handle.clone();
+            async move {
+                for chunk in ["one\ntw", "o\r\n\nth", "ree"] {
+                    writer.write_all(chunk.as_bytes()).unwrap();
+                    sleep(&handle, Duration::from_millis(5)).await.unwrap();
+                }
+            }
+        };
+
+        let read = async {
+            let mut lines = lines(&handle, reader);
+            let mut out = Vec::new();
+
+            while let Some(line) = lines.next_line().await.unwrap() {
+                out.push(line);
+            }
+
+            // And stays at the end.
+            assert!(lines.next_line().await.unwrap().is_none());
+            assert!(lines.into_inner().is_some());
+            out
+        };
+
+        let ((), out) = join(write, read).await;
+        assert_eq!(out, ["one", "two", "", "three"]);
+    });
+}
+
+#[test]
+fn lines_long_and_invalid() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let mut writer = File::from(OwnedFd::from(writer));
+
+        let long = "x".repeat(20 << 10);
+        let mut data = format!("{}\n", long).into_bytes();
+        data.extend_from_slice(b"\xff\xfe\nlast\n");
+
+        // Fits the pipe, so it can be written before reading.
+        writer.write_all(&data).unwrap();
+        drop(writer);
+
+        let mut lines = lines(&handle, reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), long);
+
+        let err = lines.next_line().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // The invalid line is skipped.
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "last");
+        assert!(lines.next_line().await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn lines_keep_the_buffer_on_error() {
+    run(|handle| async move {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut writer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (reader, _) = listener.accept().unwrap();
+
+        writer.write_all(b"one\npart").unwrap();
+
+        let mut lines = lines(&handle, reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "one");
+
+        // Reset the connection, the next read fails.
+        let linger = libc::linger { l_onoff: 1, l_linger: 0 };
+        let ret = unsafe {
+            libc::setsockopt(
+                writer.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_LINGER,
+                (&linger as *const libc::linger).cast(),
+                std::mem::size_of::<libc::linger>() as _
+            )
+        };
+        assert_eq!(ret, 0);
+        drop(writer);
+
+        let err = lines.next_line().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        // The partial line read before is still there.
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "part");
+        assert!(lines.next_line().await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn lines_from_stdin() {
+    let (reader, writer) = pipe().unwrap();
+    let mut writer = File::from(OwnedFd::from(writer));
+    writer.write_all(b"from stdin\n").unwrap();
+    drop(writer);
+
+    // No other test in this binary uses stdin.
+    let saved = unsafe { libc::dup(0) };
+    assert!(saved >= 0);
+    assert_eq!(unsafe { libc::dup2(reader.as_raw_fd(), 0) }, 0);
+    drop(reader);
+
+    let out = run(|handle| async move {
+        let mut lines = Lines::stdin(&handle);
+        (lines.next_line().await, lines.next_line().await)
+    });
+
+    unsafe {
+        libc::dup2(saved, 0);
+        libc::close(saved);
+    }
+
+    assert_eq!(out.0.unwrap().as_deref(), Some("from stdin"));
+    assert!(out.1.unwrap().is_none());
+}
@@ -0,0 +1,107 @@
+use std::thread;
+use std::time::Duration;
+use std::future::{ poll_fn, Future };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::task::{ Context, Poll, Waker };
+use ritsu::{ pipe, Proactor };
+use ritsu::time::sleep;
+use ritsu::actions::io::{ read_buf, write_buf, UnsafeFd };
+
+
+#[test]
+fn io_counters() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let (reader, writer) = pipe().unwrap();
+
+    ritsu::block_on(&mut proactor, async {
+        let mut writer = Some(writer);
+        let (_, _, n) = write_buf(&handle, &mut writer, &b"metrics"[..], None).await.unwrap();
+        assert_eq!(n, 7);
+
+        let mut reader = Some(reader);
+        let (_, buf, n) = read_buf(&handle, &mut reader, Vec::with_capacity(4), None).await.unwrap();
+        assert_eq!((n, &buf[..]), (4, &b"metr"[..]));
+    }).unwrap();
+
+    let metrics = proactor.metrics();
+    assert_eq!(metrics.bytes_written(), 7);
+    assert_eq!(metrics.bytes_read(), 4);
+    assert!(metrics.sqes_pushed() >= 2);
+    assert!(metrics.submits() >= 1);
+    assert!(metrics.cqes() >= 2);
+    assert_eq!(metrics.ebusy_retries(), 0);
+    assert_eq!(metrics.cq_overflow(), 0);
+    assert_eq!(metrics.overflow_flushes(), 0);
+
+    // A failed action counts no bytes.
+    let mut closed = Some(unsafe { UnsafeFd::new(-1) });
+    ritsu::block_on(&mut proactor, async {
+        assert!(read_buf(&handle, &mut closed, Vec::with_capacity(1), None).await.is_err());
+    }).unwrap();
+    assert_eq!(proactor.metrics().bytes_read(), 4);
+}
+
+#[test]
+fn park_and_in_flight() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let mut cx = Context::from_waker(Waker::noop());
+
+    // The first park pushes the eventfd read, which stays in flight
+    // as nothing wakes the proactor.
+    proactor.park(Some(Duration::from_millis(1))).ok();
+    let baseline = proactor.metrics().in_flight();
+    let parks = proactor.metrics().parks();
+
+    let mut pending = Box::pin(sleep(&handle, Duration::from_millis(20)));
+    assert!(pending.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(proactor.metrics().in_flight(), baseline + 1);
+
+    while pending.as_mut().poll(&mut cx).is_pending() {
+        // Times out with nothing completed.
+        proactor.park(Some(Duration::from_millis(50))).ok();
+    }
+
+    let metrics = proactor.metrics();
+    assert_eq!(metrics.in_flight(), baseline);
+    assert!(metrics.parks() > parks);
+    assert!(metrics.park_time() >= Duration::from_millis(15));
+}
+
+#[test]
+fn wakes() {
+    let mut proactor = Proactor::new().unwrap();
+    let woken = Arc::new(AtomicBool::new(false));
+    let mut thread = None;
+
+    ritsu::block_on(&mut proactor, poll_fn(|cx| {
+        if woken.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        if thread.is_none() {
+            let waker = cx.waker().clone();
+            let woken = woken.clone();
+            thread = Some(thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                woken.store(true, Ordering::Release);
+                waker.wake();
+            }));
+        }
+
+        Poll::Pending
+    })).unwrap();
+    thread.unwrap().join().unwrap();
+
+    let metrics = proactor.metrics();
+    assert_eq!(metrics.wakes(), 1);
+    assert!(metrics.parks() >= 2);
+
+    let debug = format!("{:?}", metrics);
+    assert!(debug.starts_with("Metrics {"));
+    for field in ["sqes_pushed", "wakes", "park_time", "in_flight", "bytes_read", "bytes_written"] {
+        assert!(debug.contains(field), "{} missing from {}", field, debug);
+    }
+}
@@ -0,0 +1,45 @@
+use std::thread;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::Duration;
+use std::future::Future;
+use std::task::{ Context, Waker };
+use ritsu::{ Proactor, OpObserver, OpInfo, OpCompletion };
+use ritsu::time::sleep;
+
+
+#[derive(Default)]
+struct Recorder(RefCell<Vec<(&'static str, i32, Duration)>>);
+
+impl OpObserver for Recorder {
+    fn on_complete(&self, op: &OpInfo, completion: &OpCompletion) {
+        self.0.borrow_mut().push((op.name(), completion.result, completion.latency));
+    }
+}
+
+#[test]
+fn latency_starts_at_submit() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+    let recorder = Rc::new(Recorder::default());
+    proactor.set_observer(Some(recorder.clone()));
+
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut pending = Box::pin(sleep(&handle, Duration::from_millis(5)));
+    assert!(pending.as_mut().poll(&mut cx).is_pending());
+
+    // Queued, but not submitted yet.
+    thread::sleep(Duration::from_millis(50));
+
+    while pending.as_mut().poll(&mut cx).is_pending() {
+        proactor.park(Some(Duration::from_millis(100))).ok();
+    }
+
+    let ops = recorder.0.borrow();
+    assert_eq!(ops.len(), 1);
+
+    let (name, result, latency) = ops[0];
+    assert_eq!(name, "timeout");
+    assert_eq!(result, -libc::ETIME);
+    assert!(latency < Duration::from_millis(50), "{:?}", latency);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::fs;
+use std::io::{ Read, Write };
+use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use ritsu::actions::fs::{ OpenOptions, ResolveFlags };
+use common::{ run, TempDir };
+
+
+#[test]
+fn open_options_flag_matrix() {
+    let dir = TempDir::new("open-matrix");
+    let path = dir.join("file");
+    let path = path.as_path();
+
+    run(|handle| async move {
+        // Nothing to open for.
+        let err = OpenOptions::new().open(&handle, path).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        // Creating needs write access.
+        let err = OpenOptions::new().read(true).create(true)
+            .open(&handle, path).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        let err = OpenOptions::new().read(true).open(&handle, path).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+
+        let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600)
+            .open(&handle, path).await.unwrap();
+        file.write_all(b"hello").unwrap();
+        drop(file);
+        assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let err = OpenOptions::new().write(true).create_new(true)
+            .open(&handle, path).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+
+        // Append without create_new can't truncate.
+        let err = OpenOptions::new().append(true).truncate(true)
+            .open(&handle, path).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        let mut file = OpenOptions::new().append(true).open(&handle, path).await.unwrap();
+        file.write_all(b" world").unwrap();
+        drop(file);
+        assert_eq!(fs::read(path).unwrap(), b"hello world");
+
+        let mut file = OpenOptions::new().read(true).open(&handle, path).await.unwrap();
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello world");
+        assert!(file.write_all(b"x").is_err());
+
+        let file = OpenOptions::new().write(true).truncate(true)
+            .open(&handle, path).await.unwrap();
+        drop(file);
+        assert_eq!(fs::read(path).unwrap(), b"");
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true)
+            .open(&handle, path).await.unwrap();
+        file.write_all(b"rw").unwrap();
+        drop(file);
+        assert_eq!(fs::read(path).unwrap(), b"rw");
+    });
+}
+
+#[test]
+fn open_with_resolve() {
+    let dir = TempDir::new("open-resolve");
+    let root = dir.path();
+    fs::create_dir(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/file"), b"data").unwrap();
+    std::os::unix::fs::symlink("sub/file", dir.join("link")).unwrap();
+
+    run(|handle| async move {
+        // A directory is opened without a mode.
+        let sub = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY)
+            .resolve(ResolveFlags::NO_XDEV)
+            .open(&handle, &root.join("sub"))
+            .await
+            .unwrap();
+        assert!(sub.metadata().unwrap().is_dir());
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .resolve(ResolveFlags::NO_SYMLINKS)
+            .open(&handle, &root.join("sub/file"))
+            .await
+            .unwrap();
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "data");
+
+        let err = OpenOptions::new()
+            .read(true)
+            .resolve(ResolveFlags::NO_SYMLINKS)
+            .open(&handle, &root.join("link"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+
+        // Relative to the cwd, an absolute path escapes.
+        let err = OpenOptions::new()
+            .read(true)
+            .resolve(ResolveFlags::BENEATH)
+            .open(&handle, Path::new("/"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EXDEV));
+
+        // `O_TMPFILE` creates a file and keeps the mode.
+        let tmp = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_TMPFILE)
+            .mode(0o600)
+            .resolve(ResolveFlags::NO_XDEV)
+            .open(&handle, &root.join("sub"))
+            .await;
+
+        match tmp {
+            Ok(tmp) => assert_eq!(tmp.metadata().unwrap().permissions().mode() & 0o777, 0o600),
+            Err(err) => assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP))
+        }
+    });
+}
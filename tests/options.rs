@@ -0,0 +1,138 @@
+mod common;
+
+use std::io;
+use std::cell::RefCell;
+use std::time::Duration;
+use std::task::{ Context, Poll, Waker };
+use std::future::Future;
+use std::os::unix::io::AsRawFd;
+use io_uring::{ types, opcode, squeue };
+use ritsu::{ pipe, Handle, LocalHandle, Proactor };
+use ritsu::time::sleep;
+use ritsu::actions::{ nop, nop_with, with_timeout, PushError };
+use ritsu::actions::io::{ read_buf, write_buf, write_buf_with };
+use ritsu::actions::options::{ ActionOptions, RwFlags };
+use common::{ run, TempDir };
+
+
+/// Records the opcode, flags and op flags of every pushed entry.
+struct Recorder {
+    handle: LocalHandle,
+    pushed: RefCell<Vec<(u8, u8, u32)>>
+}
+
+impl Recorder {
+    fn record(&self, entries: &[squeue::Entry]) {
+        for entry in entries {
+            // `io_uring_sqe` starts with the opcode and the flags,
+            // the op flags are at offset 28.
+            let raw = unsafe { std::mem::transmute::<squeue::Entry, [u8; 64]>(entry.clone()) };
+            let op_flags = u32::from_ne_bytes([raw[28], raw[29], raw[30], raw[31]]);
+            self.pushed.borrow_mut().push((raw[0], raw[1], op_flags));
+        }
+    }
+
+    fn take(&self) -> Vec<(u8, u8, u32)> {
+        self.pushed.borrow_mut().drain(..).collect()
+    }
+}
+
+impl Handle for &'_ Recorder {
+    unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        self.record(std::slice::from_ref(entry));
+        self.handle.push(entry)
+    }
+
+    unsafe fn push_multiple(&self, entries: &[squeue::Entry]) -> io::Result<()> {
+        self.record(entries);
+        self.handle.push_multiple(entries)
+    }
+}
+
+const ASYNC: u8 = squeue::Flags::ASYNC.bits();
+const DRAIN: u8 = squeue::Flags::IO_DRAIN.bits();
+const LINK: u8 = squeue::Flags::IO_LINK.bits();
+
+#[test]
+fn drain_does_not_wait_for_the_wake_read() {
+    let mut proactor = Proactor::new().unwrap();
+    let handle = proactor.handle();
+
+    // A park that times out leaves the eventfd read in flight.
+    proactor.park(Some(Duration::from_millis(1))).unwrap();
+
+    let mut options = ActionOptions::new();
+    options.drain(true);
+    let mut action = Box::pin(nop_with(&handle, &options));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(action.as_mut().poll(&mut cx).is_pending());
+
+    for _ in 0..20 {
+        proactor.park(Some(Duration::from_millis(50))).unwrap();
+
+        if let Poll::Ready(ret) = action.as_mut().poll(&mut cx) {
+            return ret.unwrap();
+        }
+    }
+
+    panic!("the drained entry never ran");
+}
+
+#[test]
+fn options_only_apply_to_their_own_action() {
+    let dir = TempDir::new("options");
+
+    run(|handle| async move {
+        let recorder = Recorder { handle: handle.clone(), pushed: RefCell::new(Vec::new()) };
+        let dsync = RwFlags::DSYNC.bits();
+
+        let mut options = ActionOptions::new();
+        options.force_async(true).rw_flags(RwFlags::DSYNC);
+
+        let file = std::fs::File::create(dir.join("file")).unwrap();
+        let (file, ..) = write_buf_with(&recorder, &mut Some(file), &b"with"[..], None, &options)
+            .await
+            .unwrap();
+
+        // Later actions through the same handle are pushed as built.
+        write_buf(&recorder, &mut Some(file), &b"out"[..], None).await.unwrap();
+        nop(&recorder).await.unwrap();
+        sleep(&recorder, Duration::from_millis(1)).await.unwrap();
+        assert_eq!(recorder.take(), [
+            (opcode::Write::CODE, ASYNC, dsync),
+            (opcode::Write::CODE, 0, 0),
+            (opcode::Nop::CODE, 0, 0),
+            (opcode::Timeout::CODE, 0, types::TimeoutFlags::ABS.bits())
+        ]);
+        assert_eq!(std::fs::read(dir.join("file")).unwrap(), b"without");
+
+        // The linked timeout of an entry with options keeps its own flags.
+        let (reader, writer) = pipe().unwrap();
+        let mut buf = Box::new([0u8; 8]);
+        let read_e = opcode::Read::new(types::Fd(reader.as_raw_fd()), buf.as_mut_ptr(), 8)
+            .build();
+        let action = unsafe {
+            with_timeout(&recorder, (reader, buf), options.apply(read_e), Duration::from_millis(5))
+                .map_err(PushError::into_error)
+                .unwrap()
+        };
+        let (reader, _) = action.await.unwrap_err().into_inner();
+        assert_eq!(recorder.take(), [
+            (opcode::Read::CODE, LINK | ASYNC, dsync),
+            (opcode::LinkTimeout::CODE, 0, 0)
+        ]);
+
+        // A drained entry is pushed as is, the proactor completes its wake read.
+        let mut drained = ActionOptions::new();
+        drained.drain(true);
+        nop_with(&recorder, &drained).await.unwrap();
+
+        write_buf(&recorder, &mut Some(writer), &b"x"[..], None).await.unwrap();
+        read_buf(&recorder, &mut Some(reader), Vec::with_capacity(1), None).await.unwrap();
+        assert_eq!(recorder.take(), [
+            (opcode::Nop::CODE, DRAIN, 0),
+            (opcode::Write::CODE, 0, 0),
+            (opcode::Read::CODE, 0, 0)
+        ]);
+    });
+}
@@ -0,0 +1,97 @@
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{ Context, Poll };
+use futures_task::noop_waker_ref;
+use io_uring::{ opcode, IoUring };
+use ritsu::Proactor;
+use ritsu::actions::{ self, Action, PushError };
+
+
+const CQ_SIZE: u32 = 8;
+
+fn small_proactor() -> Proactor {
+    let mut builder = IoUring::builder();
+    builder.setup_cqsize(CQ_SIZE);
+    Proactor::with_builder(builder, 4).unwrap()
+}
+
+fn push_nops(proactor: &Proactor, n: usize) -> Vec<Action<()>> {
+    let handle = proactor.handle();
+
+    (0..n)
+        .map(|_| unsafe {
+            actions::action(&handle, (), opcode::Nop::new().build())
+                .map_err(PushError::into_error)
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn poll_completions_flushes_overflow() {
+    let mut proactor = small_proactor();
+    let mut nops = push_nops(&proactor, 64);
+
+    // Nothing is left to submit after the first calls,
+    // the kept back completions must still arrive.
+    let mut count = 0;
+    for _ in 0..64 {
+        count += proactor.poll_completions().unwrap();
+    }
+
+    assert_eq!(count, 64);
+    assert!(proactor.metrics().overflow_flushes() > 0);
+    assert_eq!(proactor.metrics().cq_overflow(), 0);
+    assert_eq!(proactor.metrics().in_flight(), 0);
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+    for nop in nops.iter_mut() {
+        match Pin::new(nop).poll(&mut cx) {
+            Poll::Ready(((), cqe)) => assert_eq!(cqe.result(), 0),
+            Poll::Pending => panic!("completion was lost")
+        }
+    }
+}
+
+#[test]
+fn block_on_survives_overflow() {
+    let mut proactor = small_proactor();
+    let nops = push_nops(&proactor, 256);
+
+    let results = ritsu::block_on(&mut proactor, async move {
+        let mut results = Vec::new();
+        for nop in nops {
+            let ((), cqe) = nop.await;
+            results.push(cqe.result());
+        }
+        results
+    }).unwrap();
+
+    assert_eq!(results.len(), 256);
+    assert!(results.iter().all(|&ret| ret == 0));
+    assert_eq!(proactor.metrics().cq_overflow(), 0);
+}
+
+#[test]
+fn overflow_with_linked_chain() {
+    let mut proactor = small_proactor();
+    let handle = proactor.handle();
+
+    let chain = (0..4).fold(actions::Chain::new(), |chain, _| chain.link(opcode::Nop::new().build()));
+    let chain = unsafe {
+        chain.submit(&handle, ())
+            .map_err(PushError::into_error)
+            .unwrap()
+    };
+    let nops = push_nops(&proactor, 32);
+
+    let ((), entries) = ritsu::block_on(&mut proactor, async move {
+        for nop in nops {
+            nop.await;
+        }
+        chain.await
+    }).unwrap();
+
+    assert_eq!(entries.len(), 4);
+    assert!(entries.iter().all(|cqe| cqe.result() == 0));
+}
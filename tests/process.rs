@@ -0,0 +1,92 @@
+mod common;
+
+use std::process::{ ExitStatus, Stdio };
+use std::os::unix::process::ExitStatusExt;
+use ritsu::Handle;
+use ritsu::process::Command;
+use ritsu::actions::io::{ read_buf, write_buf, TrustedAsRawFd };
+use common::run;
+
+
+async fn read_to_end<H: Handle, T: TrustedAsRawFd>(handle: H, fd: T) -> Vec<u8> {
+    let mut fd = Some(fd);
+    let mut out = Vec::new();
+
+    loop {
+        let (fd2, buf, n) = read_buf(&handle, &mut fd, Vec::with_capacity(64), None).await.unwrap();
+
+        if n == 0 {
+            return out;
+        }
+
+        out.extend_from_slice(&buf);
+        fd = Some(fd2);
+    }
+}
+
+async fn status<H: Handle>(handle: H, command: &mut Command) -> ExitStatus {
+    command.spawn(handle).unwrap().wait().await.unwrap()
+}
+
+#[test]
+fn wait_for_exit() {
+    run(|handle| async move {
+        assert!(status(&handle, &mut Command::new("true")).await.success());
+
+        let code = status(&handle, Command::new("sh").args(["-c", "exit 3"])).await;
+        assert_eq!(code.code(), Some(3));
+
+        // Killed, and waited on again.
+        let mut child = Command::new("sleep").arg("10").spawn(&handle).unwrap();
+        child.kill().unwrap();
+        let killed = child.wait().await.unwrap();
+        assert_eq!(killed.signal(), Some(libc::SIGKILL));
+        assert_eq!(child.wait().await.unwrap(), killed);
+
+        // Killing a reaped child does nothing.
+        child.kill().unwrap();
+
+        // A missing program fails to spawn.
+        assert!(Command::new("/nonexistent/ritsu-test").spawn(&handle).is_err());
+    });
+}
+
+#[test]
+fn piped_stdio() {
+    run(|handle| async move {
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn(&handle)
+            .unwrap();
+
+        let mut stdin = child.stdin.take();
+        let (stdin, _, n) = write_buf(&handle, &mut stdin, "hello child", None).await.unwrap();
+        assert_eq!(n, 11);
+        drop(stdin);
+
+        let stdout = child.stdout.take().unwrap();
+        assert_eq!(read_to_end(&handle, stdout).await, b"hello child");
+        assert!(child.wait().await.unwrap().success());
+
+        // wait closes stdin, so a child reading it to the end exits.
+        let mut child = Command::new("sh")
+            .args(["-c", "cat >/dev/null; echo done >&2; exit 4"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn(&handle)
+            .unwrap();
+
+        let stderr = child.stderr.take().unwrap();
+        assert_eq!(child.wait().await.unwrap().code(), Some(4));
+        assert_eq!(read_to_end(&handle, stderr).await, b"done\n");
+
+        // Pipes left in the child are closed on drop.
+        let child = Command::new("true")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn(&handle)
+            .unwrap();
+        drop(child);
+    });
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::io::{ self, Read, Write };
+use std::future::Future;
+use std::task::{ Context, Waker };
+use std::time::Duration;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use ritsu::{ pipe, Handle, Readiness };
+use ritsu::time::sleep;
+use ritsu::actions::poll::{ poll, poll_multi, PollFlags };
+use common::{ run, join, next };
+
+
+fn pair() -> (UnixStream, UnixStream) {
+    let (a, b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    b.set_nonblocking(true).unwrap();
+    (a, b)
+}
+
+#[test]
+fn readiness_try_io() {
+    run(|handle| async move {
+        let (a, b) = pair();
+        let a = Readiness::new(handle.clone(), a);
+        let mut buf = [0; 16];
+
+        // A socket with buffer space is writable right away.
+        a.writable().await.unwrap();
+
+        let err = a.try_io(PollFlags::IN, |mut fd| fd.read(&mut buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        (&b).write_all(b"ping").unwrap();
+        a.readable().await.unwrap();
+        let n = a.try_io(PollFlags::IN, |mut fd| fd.read(&mut buf)).unwrap();
+        assert_eq!(&buf[..n], b"ping");
+
+        // Readiness stays cached until an operation would block.
+        assert!(a.ready(PollFlags::IN).await.unwrap().contains(PollFlags::IN));
+        let err = a.try_io(PollFlags::IN, |mut fd| fd.read(&mut buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // So this waits for the kernel again.
+        let writer = {
+            let handle = handle.clone();
+            async move {
+                sleep(&handle, Duration::from_millis(10)).await.unwrap();
+                (&b).write_all(b"pong").unwrap();
+                b
+            }
+        };
+        let reader = a.async_io(PollFlags::IN, |mut fd| fd.read(&mut buf));
+        let (b, n) = join(writer, reader).await;
+        assert_eq!(&buf[..n.unwrap()], b"pong");
+
+        drop(b);
+        let ready = a.ready(PollFlags::IN).await.unwrap();
+        assert!(ready.contains(PollFlags::IN));
+        let n = a.try_io(PollFlags::IN, |mut fd| fd.read(&mut buf)).unwrap();
+        assert_eq!(n, 0);
+    });
+}
+
+#[test]
+fn readiness_drop_removes_the_poll() {
+    run(|handle| async move {
+        let (a, _b) = pair();
+        let a = Readiness::new(handle.clone(), a);
+
+        // Park once, so the wake read is counted in both.
+        sleep(&handle, Duration::from_millis(1)).await.unwrap();
+        let in_flight = handle.metrics().unwrap().in_flight();
+
+        {
+            let mut readable = Box::pin(a.readable());
+            let mut cx = Context::from_waker(Waker::noop());
+            assert!(readable.as_mut().poll(&mut cx).is_pending());
+        }
+
+        sleep(&handle, Duration::from_millis(5)).await.unwrap();
+        assert_eq!(handle.metrics().unwrap().in_flight(), in_flight);
+    });
+}
+
+#[test]
+fn poll_oneshot_and_multishot() {
+    run(|handle| async move {
+        let (reader, writer) = pipe().unwrap();
+        let mut writer = std::fs::File::from(std::os::unix::io::OwnedFd::from(writer));
+
+        writer.write_all(b"x").unwrap();
+        let mut reader = Some(reader);
+        let (reader, ready) = poll(&handle, &mut reader, PollFlags::IN).await.unwrap();
+        assert!(ready.contains(PollFlags::IN));
+
+        let mut events = poll_multi(&handle, reader, PollFlags::IN);
+        let mut buf = [0; 8];
+
+        for _ in 0..3 {
+            let ready = next(&mut events).await.unwrap().unwrap();
+            assert!(ready.contains(PollFlags::IN));
+
+            // Consume it, the next event is only posted on the next write.
+            let fd = events.get_ref().as_raw_fd();
+            assert_eq!(unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) }, 1);
+            writer.write_all(b"x").unwrap();
+        }
+
+        drop(writer);
+        let ready = next(&mut events).await.unwrap().unwrap();
+        assert!(ready.intersects(PollFlags::IN | PollFlags::HUP));
+    });
+}
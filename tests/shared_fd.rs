@@ -0,0 +1,123 @@
+mod common;
+
+use std::{ io, mem };
+use std::io::Write;
+use std::fs::File;
+use std::time::Duration;
+use std::future::Future;
+use std::task::{ Context, Waker };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::os::unix::net::UnixStream;
+use ritsu::Handle;
+use ritsu::time::sleep;
+use ritsu::actions::io::{ read_buf, write_buf, SharedFd, TrustedAsRawFd };
+use common::{ run, join, TempDir };
+
+
+async fn read<H: Handle, T: TrustedAsRawFd>(handle: H, fd: SharedFd<T>, len: usize, offset: Option<u32>)
+    -> io::Result<Vec<u8>>
+{
+    let (_, buf, _) = read_buf(handle, &mut Some(fd), Vec::with_capacity(len), offset).await?;
+    Ok(buf)
+}
+
+async fn write<H: Handle, T: TrustedAsRawFd>(handle: H, fd: SharedFd<T>, data: &'static [u8])
+    -> io::Result<usize>
+{
+    let (_, _, n) = write_buf(handle, &mut Some(fd), data, None).await?;
+    Ok(n)
+}
+
+/// The inode behind `fd`, if it is open.
+fn inode(fd: RawFd) -> Option<libc::ino_t> {
+    let mut stat = unsafe { mem::zeroed::<libc::stat>() };
+
+    if unsafe { libc::fstat(fd, &mut stat) } == 0 {
+        Some(stat.st_ino)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn full_duplex() {
+    run(|handle| async move {
+        let (a, b) = UnixStream::pair().unwrap();
+        let a = SharedFd::new(a);
+        let b = SharedFd::new(b);
+
+        // a writes while its read is in flight, b answers once it got the message.
+        let a_side = join(
+            read(&handle, a.clone(), 16, None),
+            async {
+                sleep(&handle, Duration::from_millis(5)).await.unwrap();
+                assert_eq!(SharedFd::ref_count(&a), 2);
+                write(&handle, a.clone(), b"from a").await
+            }
+        );
+        let b_side = async {
+            let received = read(&handle, b.clone(), 16, None).await;
+            (received, write(&handle, b.clone(), b"from b").await)
+        };
+
+        let ((a_read, a_written), (b_read, b_written)) = join(a_side, b_side).await;
+        assert_eq!(a_read.unwrap(), b"from b");
+        assert_eq!(b_read.unwrap(), b"from a");
+        assert_eq!((a_written.unwrap(), b_written.unwrap()), (6, 6));
+
+        // Every action gave its clone back.
+        assert_eq!(SharedFd::ref_count(&a), 1);
+        assert!(SharedFd::try_unwrap(a).is_ok());
+
+        let b2 = b.clone();
+        assert!(SharedFd::try_unwrap(b).is_err());
+        drop(b2);
+    });
+}
+
+#[test]
+fn parallel_positional_reads() {
+    let dir = TempDir::new("shared-fd");
+    let path = dir.join("file");
+    File::create(&path).unwrap().write_all(b"0123456789abcdef").unwrap();
+
+    run(|handle| async move {
+        let file = SharedFd::new(File::open(&path).unwrap());
+
+        let (first, (second, third)) = join(
+            read(&handle, file.clone(), 4, Some(0)),
+            join(
+                read(&handle, file.clone(), 4, Some(8)),
+                read(&handle, file.clone(), 4, Some(12))
+            )
+        ).await;
+
+        assert_eq!(first.unwrap(), b"0123");
+        assert_eq!(second.unwrap(), b"89ab");
+        assert_eq!(third.unwrap(), b"cdef");
+        assert_eq!(SharedFd::ref_count(&file), 1);
+    });
+}
+
+#[test]
+fn closed_after_the_last_action() {
+    run(|handle| async move {
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let raw = a.as_raw_fd();
+        let ino = inode(raw);
+        let a = SharedFd::new(a);
+
+        let mut pending = Box::pin(read(&handle, a.clone(), 8, None));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(pending.as_mut().poll(&mut cx).is_pending());
+
+        // The action keeps the fd open.
+        drop(a);
+        sleep(&handle, Duration::from_millis(5)).await.unwrap();
+        assert_eq!(inode(raw), ino);
+
+        b.write_all(b"late").unwrap();
+        assert_eq!(pending.await.unwrap(), b"late");
+        assert_ne!(inode(raw), ino);
+    });
+}
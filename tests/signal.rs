@@ -0,0 +1,95 @@
+mod common;
+
+use std::{ mem, ptr };
+use std::future::Future;
+use std::task::{ Context, Waker };
+use ritsu::signal::{ ctrl_c, Signals };
+use common::run;
+
+
+fn is_blocked(signal: libc::c_int) -> bool {
+    unsafe {
+        let mut set = mem::zeroed::<libc::sigset_t>();
+        libc::pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut set);
+        libc::sigismember(&set, signal) == 1
+    }
+}
+
+fn set_blocked(signal: libc::c_int, blocked: bool) {
+    unsafe {
+        let mut set = mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        let how = if blocked { libc::SIG_BLOCK } else { libc::SIG_UNBLOCK };
+        libc::pthread_sigmask(how, &set, ptr::null_mut());
+    }
+}
+
+#[test]
+fn signals_restore_the_mask() {
+    run(|handle| async move {
+        assert!(!is_blocked(libc::SIGUSR1));
+
+        let mut signals = Signals::new(&handle, &[libc::SIGUSR1]).unwrap();
+        assert!(is_blocked(libc::SIGUSR1));
+
+        unsafe {
+            libc::raise(libc::SIGUSR1);
+        }
+        let info = signals.recv().await.unwrap();
+        assert_eq!(info.ssi_signo, libc::SIGUSR1 as u32);
+
+        drop(signals);
+        assert!(!is_blocked(libc::SIGUSR1));
+
+        // A signal that was blocked before stays blocked.
+        set_blocked(libc::SIGUSR2, true);
+        let signals = Signals::new(&handle, &[libc::SIGUSR1, libc::SIGUSR2]).unwrap();
+        drop(signals);
+        assert!(!is_blocked(libc::SIGUSR1));
+        assert!(is_blocked(libc::SIGUSR2));
+        set_blocked(libc::SIGUSR2, false);
+    });
+}
+
+#[test]
+fn signals_share_the_mask() {
+    run(|handle| async move {
+        assert!(!is_blocked(libc::SIGWINCH));
+
+        let first = Signals::new(&handle, &[libc::SIGWINCH]).unwrap();
+        let mut second = Signals::new(&handle, &[libc::SIGWINCH, libc::SIGURG]).unwrap();
+
+        // The other one still needs it blocked.
+        drop(first);
+        assert!(is_blocked(libc::SIGWINCH));
+
+        unsafe {
+            libc::raise(libc::SIGWINCH);
+        }
+        let info = second.recv().await.unwrap();
+        assert_eq!(info.ssi_signo, libc::SIGWINCH as u32);
+
+        drop(second);
+        assert!(!is_blocked(libc::SIGWINCH));
+        assert!(!is_blocked(libc::SIGURG));
+    });
+}
+
+#[test]
+fn ctrl_c_restores_the_mask() {
+    run(|handle| async move {
+        assert!(!is_blocked(libc::SIGINT));
+
+        let mut interrupted = Box::pin(ctrl_c(&handle));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(interrupted.as_mut().poll(&mut cx).is_pending());
+        assert!(is_blocked(libc::SIGINT));
+
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+        interrupted.await.unwrap();
+        assert!(!is_blocked(libc::SIGINT));
+    });
+}
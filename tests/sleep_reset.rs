@@ -0,0 +1,71 @@
+//! Alone in its binary, so the allocation count only sees this test.
+
+mod common;
+
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::sync::atomic::{ AtomicIsize, Ordering };
+use std::time::{ Duration, Instant };
+use ritsu::time::sleep;
+use common::run;
+
+
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+#[test]
+fn sleep_reset_releases_updates() {
+    run(|handle| async move {
+        let mut timer = sleep(&handle, Duration::from_secs(60));
+
+        // Arm it.
+        assert!(poll_once(&mut timer).is_none());
+
+        // Warm up, so lazily allocated state is not counted.
+        for _ in 0..8 {
+            timer.reset(Instant::now() + Duration::from_secs(60)).unwrap();
+            sleep(&handle, Duration::from_micros(100)).await.unwrap();
+        }
+
+        let before = LIVE.load(Ordering::Relaxed);
+
+        for _ in 0..200 {
+            timer.reset(Instant::now() + Duration::from_secs(60)).unwrap();
+            sleep(&handle, Duration::from_micros(100)).await.unwrap();
+        }
+
+        let grown = LIVE.load(Ordering::Relaxed) - before;
+        assert!(grown < 50, "{} allocations kept by 200 resets", grown);
+
+        let start = Instant::now();
+        timer.reset(start + Duration::from_millis(10)).unwrap();
+        timer.await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    });
+}
+
+/// Poll once with a waker that does nothing.
+fn poll_once<F: std::future::Future + Unpin>(f: &mut F) -> Option<F::Output> {
+    use std::task::{ Context, Poll, Waker };
+
+    let mut cx = Context::from_waker(Waker::noop());
+    match std::pin::Pin::new(f).poll(&mut cx) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None
+    }
+}
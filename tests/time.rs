@@ -0,0 +1,108 @@
+mod common;
+
+use std::pin::Pin;
+use std::future::{ poll_fn, Future };
+use std::task::{ Context, Poll, Waker };
+use std::time::{ Duration, Instant };
+use ritsu::Proactor;
+use ritsu::time::{ sleep, sleep_until, interval };
+use common::run;
+
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn sleep_waits_for_the_deadline() {
+    run(|handle| async move {
+        let start = Instant::now();
+        sleep(&handle, 20 * MS).await.unwrap();
+        assert!(start.elapsed() >= 20 * MS);
+
+        // A deadline in the past completes right away.
+        let start = Instant::now();
+        sleep_until(&handle, start - 20 * MS).await.unwrap();
+        assert!(start.elapsed() < 20 * MS);
+    });
+}
+
+#[test]
+fn sleep_cancel() {
+    run(|handle| async move {
+        let mut timer = sleep(&handle, Duration::from_secs(60));
+        timer.cancel().unwrap();
+        let err = timer.await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ECANCELED));
+    });
+}
+
+#[test]
+fn interval_ticks_on_schedule() {
+    run(|handle| async move {
+        let start = Instant::now();
+        let mut ticks = interval(&handle, 10 * MS);
+
+        let mut last = start;
+        for i in 1..=3 {
+            let tick = ticks.tick().await.unwrap();
+            assert!(tick > last);
+            assert!(Instant::now() >= start + i * 10 * MS);
+            last = tick;
+        }
+    });
+}
+
+#[test]
+fn timers_on_the_wheel() {
+    let mut proactor = Proactor::new().unwrap();
+    proactor.enable_timer_wheel();
+    let handle = proactor.handle();
+
+    ritsu::block_on(&mut proactor, async move {
+        let start = Instant::now();
+        sleep(&handle, 20 * MS).await.unwrap();
+        assert!(start.elapsed() >= 20 * MS);
+
+        let mut timer = sleep(&handle, Duration::from_secs(60));
+        timer.cancel().unwrap();
+        assert!(timer.await.is_err());
+
+        // Armed on the wheel, then moved earlier.
+        let mut timer = sleep(&handle, Duration::from_secs(60));
+        assert!(poll_fn(|cx| Poll::Ready(Pin::new(&mut timer).poll(cx).is_pending())).await);
+        let start = Instant::now();
+        timer.reset(start + 10 * MS).unwrap();
+        timer.await.unwrap();
+        assert!(start.elapsed() >= 10 * MS);
+
+        let mut ticks = interval(&handle, 5 * MS);
+        let first = ticks.tick().await.unwrap();
+        let second = ticks.tick().await.unwrap();
+        assert_eq!(second - first, 5 * MS);
+    }).unwrap();
+}
+
+#[test]
+fn wheel_timers_stay_out_of_the_ring() {
+    let mut proactor = Proactor::new().unwrap();
+    proactor.enable_timer_wheel();
+    let handle = proactor.handle();
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let pushed = proactor.metrics().sqes_pushed();
+    let mut timers = (1..=100)
+        .map(|i| Box::pin(sleep(&handle, i * MS)))
+        .collect::<Vec<_>>();
+    for timer in &mut timers {
+        assert!(timer.as_mut().poll(&mut cx).is_pending());
+    }
+
+    // Only the timeout that arms the wheel goes to the kernel.
+    proactor.park(Some(Duration::ZERO)).ok();
+    assert!(proactor.metrics().sqes_pushed() - pushed < 10);
+
+    ritsu::block_on(&mut proactor, async move {
+        for timer in timers {
+            timer.await.unwrap();
+        }
+    }).unwrap();
+}